@@ -0,0 +1,125 @@
+//! Client-side pre-aggregation of metric observations into `StatisticSet`
+//! data points, so high-cardinality emitters can post one roll-up datum
+//! per flush window instead of one `MetricDatum` per observation.
+//!
+//! Percentile-sensitive metrics should bypass this and go straight
+//! through `Manager::put_metric_data`/`put_metric_data_durable`: a
+//! `StatisticSet` only carries min/max/sum/sample count, which is enough
+//! to reconstruct an average but not a percentile.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use aws_sdk_cloudwatch::types::{Dimension, MetricDatum, StandardUnit, StatisticSet};
+
+/// Identifies one aggregation bucket: a metric name, unit, and sorted
+/// dimension set. Two observations with the same key roll up into the
+/// same `StatisticSet`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct AggregationKey {
+    metric_name: String,
+    unit: Option<String>,
+    dimensions: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    minimum: f64,
+    maximum: f64,
+    sum: f64,
+    sample_count: f64,
+}
+
+impl Bucket {
+    fn new(value: f64) -> Self {
+        Self {
+            minimum: value,
+            maximum: value,
+            sum: value,
+            sample_count: 1.0,
+        }
+    }
+
+    fn accumulate(&mut self, value: f64) {
+        self.minimum = self.minimum.min(value);
+        self.maximum = self.maximum.max(value);
+        self.sum += value;
+        self.sample_count += 1.0;
+    }
+}
+
+/// Accumulates metric observations keyed by (metric name, unit, sorted
+/// dimension set) over a flush window, to be rolled up into
+/// `StatisticSet` data points by `Manager::flush_aggregated_metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct Aggregator {
+    buckets: Arc<Mutex<HashMap<AggregationKey, Bucket>>>,
+}
+
+impl Aggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accumulates one observation of `value` for `metric_name`, tagged
+    /// with `dimensions` and `unit`.
+    pub fn record(
+        &self,
+        metric_name: &str,
+        mut dimensions: Vec<(String, String)>,
+        unit: Option<&str>,
+        value: f64,
+    ) {
+        dimensions.sort();
+        let key = AggregationKey {
+            metric_name: metric_name.to_string(),
+            unit: unit.map(|u| u.to_string()),
+            dimensions,
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets
+            .entry(key)
+            .and_modify(|b| b.accumulate(value))
+            .or_insert_with(|| Bucket::new(value));
+    }
+
+    /// Returns the number of distinct (metric, unit, dimension-set)
+    /// buckets currently accumulated.
+    pub fn pending_count(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// Drains every accumulated bucket, converting each into a single
+    /// `MetricDatum` carrying a `StatisticSet`.
+    pub fn drain(&self) -> Vec<MetricDatum> {
+        let drained: HashMap<AggregationKey, Bucket> =
+            std::mem::take(&mut *self.buckets.lock().unwrap());
+
+        drained
+            .into_iter()
+            .map(|(key, bucket)| {
+                let mut builder = MetricDatum::builder()
+                    .metric_name(key.metric_name)
+                    .statistic_values(
+                        StatisticSet::builder()
+                            .minimum(bucket.minimum)
+                            .maximum(bucket.maximum)
+                            .sum(bucket.sum)
+                            .sample_count(bucket.sample_count)
+                            .build()
+                            .expect("all StatisticSet fields are always set above"),
+                    );
+                if let Some(unit) = key.unit {
+                    builder = builder.unit(StandardUnit::from(unit.as_str()));
+                }
+                for (name, value) in key.dimensions {
+                    builder = builder.dimensions(Dimension::builder().name(name).value(value).build());
+                }
+                builder.build()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,380 @@
+//! A small hand-rolled JSON Schema (draft 2020-12 subset) validator for
+//! the CloudWatch agent [`Config`](super::Config), so `Config::validate`
+//! can catch a malformed config before it's pushed to an instance rather
+//! than failing at agent-start time on the box.
+//!
+//! This intentionally implements only the subset of JSON Schema the agent
+//! config needs (`type`, `required`, `properties`,
+//! `additionalProperties`, `items`, `enum`, `pattern`, `minimum`,
+//! `maximum`), walking the schema and the `serde_json::Value` produced by
+//! `Config::encode_json` in lockstep and collecting every failing
+//! instance path instead of stopping at the first one.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use regex::Regex;
+use serde_json::Value;
+
+/// Embedded schema for the three top-level sections of the CloudWatch
+/// agent config this crate writes.
+/// ref. https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch-Agent-Configuration-File-Details.html
+const CONFIG_SCHEMA_JSON: &str = r#"
+{
+  "type": "object",
+  "additionalProperties": false,
+  "properties": {
+    "agent": {
+      "type": "object",
+      "required": ["metrics_collection_interval", "logfile"],
+      "additionalProperties": true,
+      "properties": {
+        "metrics_collection_interval": { "type": "integer", "minimum": 1 },
+        "region": { "type": "string" },
+        "logfile": { "type": "string" },
+        "debug": { "type": "boolean" }
+      }
+    },
+    "logs": {
+      "type": "object",
+      "additionalProperties": true,
+      "properties": {
+        "force_flush_interval": { "type": "integer", "minimum": 1 },
+        "logs_collected": {
+          "type": "object",
+          "additionalProperties": true,
+          "properties": {
+            "files": {
+              "type": "object",
+              "additionalProperties": true,
+              "properties": {
+                "collect_list": {
+                  "type": "array",
+                  "items": {
+                    "type": "object",
+                    "required": ["log_group_name", "log_stream_name", "file_path"],
+                    "additionalProperties": true,
+                    "properties": {
+                      "log_group_name": { "type": "string", "pattern": "^[\\w/.#-]{1,512}$" },
+                      "log_stream_name": { "type": "string", "pattern": "^[^:*]{1,512}$" },
+                      "file_path": { "type": "string" },
+                      "timestamp_format": { "type": "string" },
+                      "timezone": { "type": "string", "enum": ["UTC", "Local"] },
+                      "auto_removal": { "type": "boolean" },
+                      "retention_in_days": { "type": "integer", "minimum": 1 }
+                    }
+                  }
+                }
+              }
+            }
+          }
+        }
+      }
+    },
+    "metrics": {
+      "type": "object",
+      "required": ["namespace", "metrics_collected", "force_flush_interval"],
+      "additionalProperties": true,
+      "properties": {
+        "namespace": { "type": "string", "pattern": "^.{1,255}$" },
+        "force_flush_interval": { "type": "integer", "minimum": 1 },
+        "append_dimensions": { "type": "object", "additionalProperties": true },
+        "aggregation_dimensions": { "type": "array", "items": { "type": "array", "items": { "type": "string" } } },
+        "metrics_collected": { "type": "object", "additionalProperties": true }
+      }
+    }
+  }
+}
+"#;
+
+/// A parsed, validate-able subset of JSON Schema.
+#[derive(Debug, Clone)]
+enum Schema {
+    Object {
+        required: Vec<String>,
+        properties: HashMap<String, Schema>,
+        additional: bool,
+    },
+    Array {
+        items: Box<Schema>,
+    },
+    String {
+        enum_values: Option<Vec<String>>,
+        pattern: Option<String>,
+    },
+    Integer {
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+    },
+    Number {
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+    },
+    Boolean,
+    /// An unconstrained "anything goes" schema (e.g., `additionalProperties: true`).
+    Any,
+}
+
+fn parse_schema(v: &Value) -> Schema {
+    let ty = v.get("type").and_then(Value::as_str).unwrap_or("any");
+    match ty {
+        "object" => {
+            let required = v
+                .get("required")
+                .and_then(Value::as_array)
+                .map(|a| {
+                    a.iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            let properties = v
+                .get("properties")
+                .and_then(Value::as_object)
+                .map(|props| {
+                    props
+                        .iter()
+                        .map(|(k, schema)| (k.clone(), parse_schema(schema)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let additional = match v.get("additionalProperties") {
+                Some(Value::Bool(b)) => *b,
+                _ => true,
+            };
+            Schema::Object {
+                required,
+                properties,
+                additional,
+            }
+        }
+        "array" => {
+            let items = v
+                .get("items")
+                .map(parse_schema)
+                .unwrap_or(Schema::Any);
+            Schema::Array {
+                items: Box::new(items),
+            }
+        }
+        "string" => {
+            let enum_values = v.get("enum").and_then(Value::as_array).map(|a| {
+                a.iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            });
+            let pattern = v
+                .get("pattern")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            Schema::String {
+                enum_values,
+                pattern,
+            }
+        }
+        "integer" => Schema::Integer {
+            minimum: v.get("minimum").and_then(Value::as_f64),
+            maximum: v.get("maximum").and_then(Value::as_f64),
+        },
+        "number" => Schema::Number {
+            minimum: v.get("minimum").and_then(Value::as_f64),
+            maximum: v.get("maximum").and_then(Value::as_f64),
+        },
+        "boolean" => Schema::Boolean,
+        _ => Schema::Any,
+    }
+}
+
+fn schema() -> &'static Schema {
+    static SCHEMA: OnceLock<Schema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        let v: Value =
+            serde_json::from_str(CONFIG_SCHEMA_JSON).expect("CONFIG_SCHEMA_JSON is valid JSON");
+        parse_schema(&v)
+    })
+}
+
+fn regex_cache() -> &'static Mutex<HashMap<String, Regex>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let mut cache = regex_cache().lock().unwrap();
+    let re = cache
+        .entry(pattern.to_string())
+        .or_insert_with(|| Regex::new(pattern).expect("schema pattern is a valid regex"));
+    re.is_match(value)
+}
+
+/// Validates `value` against `schema`, appending every `(pointer,
+/// message)` failure found (rather than stopping at the first one) to
+/// `errors`. An absent optional property is valid; a present-but-wrong-
+/// typed property is an error.
+fn validate(schema: &Schema, value: &Value, pointer: &str, errors: &mut Vec<(String, String)>) {
+    match schema {
+        Schema::Object {
+            required,
+            properties,
+            additional,
+        } => {
+            let Some(obj) = value.as_object() else {
+                errors.push((pointer.to_string(), "expected an object".to_string()));
+                return;
+            };
+            for key in required {
+                if !obj.contains_key(key) {
+                    errors.push((
+                        format!("{pointer}/{key}"),
+                        "required property is missing".to_string(),
+                    ));
+                }
+            }
+            for (key, field_value) in obj {
+                let field_pointer = format!("{pointer}/{key}");
+                match properties.get(key) {
+                    Some(field_schema) => {
+                        validate(field_schema, field_value, &field_pointer, errors)
+                    }
+                    None if !*additional => errors.push((
+                        field_pointer,
+                        "additional properties are not allowed here".to_string(),
+                    )),
+                    None => {}
+                }
+            }
+        }
+        Schema::Array { items } => {
+            let Some(arr) = value.as_array() else {
+                errors.push((pointer.to_string(), "expected an array".to_string()));
+                return;
+            };
+            for (i, item) in arr.iter().enumerate() {
+                validate(items, item, &format!("{pointer}/{i}"), errors);
+            }
+        }
+        Schema::String {
+            enum_values,
+            pattern,
+        } => {
+            let Some(s) = value.as_str() else {
+                errors.push((pointer.to_string(), "expected a string".to_string()));
+                return;
+            };
+            if let Some(values) = enum_values {
+                if !values.iter().any(|v| v == s) {
+                    errors.push((
+                        pointer.to_string(),
+                        format!("'{s}' is not one of {values:?}"),
+                    ));
+                }
+            }
+            if let Some(pattern) = pattern {
+                if !matches_pattern(pattern, s) {
+                    errors.push((
+                        pointer.to_string(),
+                        format!("'{s}' does not match pattern '{pattern}'"),
+                    ));
+                }
+            }
+        }
+        Schema::Integer { minimum, maximum } => {
+            let Some(n) = value.as_i64().map(|n| n as f64).or_else(|| value.as_f64()) else {
+                errors.push((pointer.to_string(), "expected an integer".to_string()));
+                return;
+            };
+            if value.as_i64().is_none() {
+                errors.push((pointer.to_string(), "expected an integer".to_string()));
+            }
+            check_bounds(n, *minimum, *maximum, pointer, errors);
+        }
+        Schema::Number { minimum, maximum } => {
+            let Some(n) = value.as_f64() else {
+                errors.push((pointer.to_string(), "expected a number".to_string()));
+                return;
+            };
+            check_bounds(n, *minimum, *maximum, pointer, errors);
+        }
+        Schema::Boolean => {
+            if value.as_bool().is_none() {
+                errors.push((pointer.to_string(), "expected a boolean".to_string()));
+            }
+        }
+        Schema::Any => {}
+    }
+}
+
+fn check_bounds(
+    n: f64,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    pointer: &str,
+    errors: &mut Vec<(String, String)>,
+) {
+    if let Some(min) = minimum {
+        if n < min {
+            errors.push((pointer.to_string(), format!("{n} is less than minimum {min}")));
+        }
+    }
+    if let Some(max) = maximum {
+        if n > max {
+            errors.push((
+                pointer.to_string(),
+                format!("{n} is greater than maximum {max}"),
+            ));
+        }
+    }
+}
+
+/// Validates `value` (the `serde_json::Value` produced by
+/// `Config::encode_json`) against the embedded CloudWatch agent config
+/// schema, returning every failing instance pointer and message.
+pub(crate) fn validate_config(value: &Value) -> Vec<(String, String)> {
+    let mut errors = Vec::new();
+    validate(schema(), value, "", &mut errors);
+    errors
+}
+
+#[test]
+fn test_validate_config_known_good() {
+    let good = serde_json::json!({
+        "agent": {
+            "metrics_collection_interval": 60,
+            "logfile": "/opt/aws/amazon-cloudwatch-agent/logs/amazon-cloudwatch-agent.log"
+        }
+    });
+    assert_eq!(validate_config(&good), Vec::<(String, String)>::new());
+}
+
+#[test]
+fn test_validate_config_known_bad() {
+    let bad = serde_json::json!({
+        "agent": {
+            "metrics_collection_interval": 0,
+            "logfile": "/opt/aws/amazon-cloudwatch-agent/logs/amazon-cloudwatch-agent.log"
+        },
+        "logs": {
+            "logs_collected": {
+                "files": {
+                    "collect_list": [
+                        { "log_group_name": "g", "file_path": "/var/log/x.log" }
+                    ]
+                }
+            }
+        }
+    });
+    let errors = validate_config(&bad);
+    let pointers: Vec<&str> = errors.iter().map(|(pointer, _)| pointer.as_str()).collect();
+    assert!(
+        pointers.contains(&"/agent/metrics_collection_interval"),
+        "expected a minimum-violation pointer, got {pointers:?}"
+    );
+    assert!(
+        pointers.contains(&"/logs/logs_collected/files/collect_list/0/log_stream_name"),
+        "expected a missing-required-property pointer, got {pointers:?}"
+    );
+}
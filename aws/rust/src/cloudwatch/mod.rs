@@ -1,31 +1,91 @@
+pub mod aggregator;
+mod config_schema;
+pub mod spool;
+pub mod writer;
+
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, File},
     io::{self, Write},
     path::Path,
+    sync::Arc,
 };
 
 use crate::errors::{self, Error, Result};
 use aws_sdk_cloudwatch::{types::MetricDatum, Client as MetricsClient};
 use aws_sdk_cloudwatchlogs::{
-    operation::{create_log_group::CreateLogGroupError, delete_log_group::DeleteLogGroupError},
+    operation::{
+        create_log_group::CreateLogGroupError, create_log_stream::CreateLogStreamError,
+        delete_log_group::DeleteLogGroupError, put_log_events::PutLogEventsError,
+        stop_query::StopQueryError,
+    },
+    types::{FilteredLogEvent, InputLogEvent, MetricTransformation, QueryStatus},
     Client as LogsClient,
 };
 use aws_smithy_runtime_api::client::result::SdkError;
 use aws_types::SdkConfig as AwsSdkConfig;
+use futures::Stream;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tokio::time::{sleep, Duration};
+use tokio::{
+    sync::Mutex,
+    time::{sleep, Duration},
+};
 
 /// TODO: bump up to 1,000
 /// ref. https://aws.amazon.com/about-aws/whats-new/2022/08/amazon-cloudwatch-metrics-increases-throughput/
 const BATCH_SIZE: usize = 950;
 
+/// Service limit on the number of log events in a single `PutLogEvents` call.
+/// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutLogEvents.html
+const MAX_LOG_EVENTS_PER_BATCH: usize = 10_000;
+
+/// Service limit on the total payload size of a single `PutLogEvents` call.
+/// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutLogEvents.html
+const MAX_LOG_EVENTS_BYTES_PER_BATCH: usize = 1_048_576;
+
+/// Per AWS docs, each log event carries 26 bytes of overhead in addition to
+/// its message length when computing the 1 MiB batch size limit.
+const LOG_EVENT_OVERHEAD_BYTES: usize = 26;
+
+/// Bounds retries against a stale `sequenceToken` before giving up.
+const MAX_PUT_LOG_EVENTS_ATTEMPTS: u32 = 5;
+
+/// Target number of events yielded per chunk from `tail_log_events`, kept
+/// small relative to the `FilterLogEvents` page size so a slow consumer
+/// sees steady progress rather than waiting on whole pages.
+const TAIL_CHUNK_SIZE: usize = 100;
+
+/// Initial delay between `get_query_results` polls for a Logs Insights
+/// query, doubled on every non-terminal poll up to `MAX_QUERY_POLL_INTERVAL`.
+const INITIAL_QUERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Upper bound on the `get_query_results` poll interval.
+const MAX_QUERY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Bounds the number of `get_query_results` polls before giving up on a
+/// query that never reaches a terminal status.
+const MAX_QUERY_POLL_ATTEMPTS: u32 = 120;
+
 /// Implements AWS CloudWatch manager.
 #[derive(Debug, Clone)]
 pub struct Manager {
     pub region: String,
     metrics_cli: MetricsClient,
     logs_cli: LogsClient,
+
+    /// Tracks the last-seen `sequenceToken` per (log group, log stream) so
+    /// `put_log_events` can resupply it on the next call without the caller
+    /// having to track it themselves.
+    log_stream_tokens: Arc<Mutex<HashMap<(String, String), String>>>,
+
+    /// Durable on-disk spool backing `put_metric_data_durable`. `None`
+    /// until `with_metric_spool` is called.
+    spool: Option<Arc<spool::MetricSpool>>,
+
+    /// Client-side pre-aggregation backing `record_metric`. `None` until
+    /// `with_aggregator` is called.
+    aggregator: Option<Arc<aggregator::Aggregator>>,
 }
 
 impl Manager {
@@ -36,7 +96,149 @@ impl Manager {
             region: shared_config.region().unwrap().to_string(),
             metrics_cli,
             logs_cli,
+            log_stream_tokens: Arc::new(Mutex::new(HashMap::new())),
+            spool: None,
+            aggregator: None,
+        }
+    }
+
+    /// Enables durable metric submission backed by an on-disk spool at
+    /// `spool_dir`, and spawns a background task that drains it every
+    /// `flush_interval`. Reopening the same `spool_dir` after a restart
+    /// automatically picks up whatever the previous process left behind,
+    /// since the directory itself is the queue.
+    pub fn with_metric_spool(mut self, spool_dir: &str, flush_interval: Duration) -> Result<Self> {
+        let spool = Arc::new(spool::MetricSpool::new(spool_dir)?);
+        self.spool = Some(spool.clone());
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                sleep(flush_interval).await;
+                spool
+                    .drain_once(|namespace, data| {
+                        let manager = manager.clone();
+                        async move { manager.put_metric_data(&namespace, data).await }
+                    })
+                    .await;
+            }
+        });
+
+        Ok(self)
+    }
+
+    /// Enqueues `data` to the durable metric spool and returns immediately;
+    /// delivery happens on the background flush task (or the next call to
+    /// `flush`). Requires `with_metric_spool` to have been called first.
+    pub async fn put_metric_data_durable(
+        &self,
+        namespace: &str,
+        data: Vec<MetricDatum>,
+    ) -> Result<()> {
+        let spool = self.spool_or_err()?;
+        log::info!(
+            "enqueuing {} CloudWatch metrics in namespace '{namespace}' to durable spool",
+            data.len()
+        );
+        for batch in data.chunks(BATCH_SIZE) {
+            spool.enqueue(namespace, batch.to_vec())?;
+        }
+        Ok(())
+    }
+
+    /// Synchronously drains every chunk currently pending in the durable
+    /// metric spool, for callers (e.g. tests, graceful shutdown) that need
+    /// delivery to complete rather than waiting on the background task.
+    pub async fn flush(&self) -> Result<()> {
+        let spool = self.spool_or_err()?;
+        let manager = self.clone();
+        spool
+            .drain_once(|namespace, data| {
+                let manager = manager.clone();
+                async move { manager.put_metric_data(&namespace, data).await }
+            })
+            .await;
+        Ok(())
+    }
+
+    /// Returns the number of metric chunks currently pending in the durable
+    /// spool, or 0 if `with_metric_spool` was never called.
+    pub fn pending_count(&self) -> usize {
+        self.spool.as_ref().map(|s| s.pending_count()).unwrap_or(0)
+    }
+
+    fn spool_or_err(&self) -> Result<Arc<spool::MetricSpool>> {
+        self.spool.clone().ok_or_else(|| Error::Other {
+            message: "durable metric spool not configured; call with_metric_spool first"
+                .to_string(),
+            retryable: false,
+        })
+    }
+
+    /// Enables client-side metric pre-aggregation. If `auto_flush` is
+    /// `Some((namespace, interval))`, a background task flushes the
+    /// aggregator into that namespace every `interval`; otherwise callers
+    /// must drive `flush_aggregated_metrics` themselves.
+    ///
+    /// Percentile-sensitive metrics should bypass aggregation and go
+    /// straight through `put_metric_data`/`put_metric_data_durable`, since
+    /// a `StatisticSet` only carries min/max/sum/sample count.
+    pub fn with_aggregator(mut self, auto_flush: Option<(String, Duration)>) -> Self {
+        let aggregator = Arc::new(aggregator::Aggregator::new());
+        self.aggregator = Some(aggregator.clone());
+
+        if let Some((namespace, interval)) = auto_flush {
+            let manager = self.clone();
+            tokio::spawn(async move {
+                loop {
+                    sleep(interval).await;
+                    if let Err(e) = manager.flush_aggregated_metrics(&namespace).await {
+                        log::warn!("failed to flush aggregated metrics ({})", e);
+                    }
+                }
+            });
+        }
+
+        self
+    }
+
+    /// Accumulates one observation of `value` for `metric_name` into the
+    /// aggregator, tagged with `dimensions` and `unit`. Requires
+    /// `with_aggregator` to have been called first.
+    pub fn record_metric(
+        &self,
+        metric_name: &str,
+        dimensions: Vec<(String, String)>,
+        unit: Option<&str>,
+        value: f64,
+    ) -> Result<()> {
+        let aggregator = self.aggregator_or_err()?;
+        aggregator.record(metric_name, dimensions, unit, value);
+        Ok(())
+    }
+
+    /// Drains every bucket currently accumulated by the aggregator,
+    /// converting each into a `MetricDatum` carrying a `StatisticSet`, and
+    /// posts them to `namespace` through the existing `put_metric_data`
+    /// batching path.
+    pub async fn flush_aggregated_metrics(&self, namespace: &str) -> Result<()> {
+        let aggregator = self.aggregator_or_err()?;
+        let data = aggregator.drain();
+        if data.is_empty() {
+            return Ok(());
         }
+        log::info!(
+            "flushing {} aggregated metric bucket(s) to namespace '{namespace}'",
+            data.len()
+        );
+        self.put_metric_data(namespace, data).await
+    }
+
+    fn aggregator_or_err(&self) -> Result<Arc<aggregator::Aggregator>> {
+        self.aggregator.clone().ok_or_else(|| Error::Other {
+            message: "metric aggregator not configured; call with_aggregator first".to_string(),
+            retryable: false,
+        })
     }
 
     pub fn metrics_client(&self) -> MetricsClient {
@@ -120,9 +322,19 @@ impl Manager {
         Ok(())
     }
 
-    /// Creates a CloudWatch log group.
+    /// Creates a CloudWatch log group, optionally applying a retention
+    /// policy (`retention_in_days`) via `put_retention_policy`. Accepted
+    /// retention values are the ones CloudWatch Logs supports (e.g., 1, 3,
+    /// 5, 7, 14, 30, 60, 90, ...); an unsupported value is rejected by the
+    /// API itself.
+    ///
     /// ref. https://docs.aws.amazon.com/AWSCloudFormation/latest/UserGuide/aws-resource-logs-loggroup.html
-    pub async fn create_log_group(&self, log_group_name: &str) -> Result<()> {
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutRetentionPolicy.html
+    pub async fn create_log_group(
+        &self,
+        log_group_name: &str,
+        retention_in_days: Option<i32>,
+    ) -> Result<()> {
         log::info!(
             "creating CloudWatch log group '{log_group_name}' in region '{}'",
             self.region
@@ -150,6 +362,102 @@ impl Manager {
         if !already_created {
             log::info!("created CloudWatch log group");
         }
+
+        if let Some(days) = retention_in_days {
+            self.put_retention_policy(log_group_name, days).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies (or overwrites) the retention policy of a log group.
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutRetentionPolicy.html
+    pub async fn put_retention_policy(
+        &self,
+        log_group_name: &str,
+        retention_in_days: i32,
+    ) -> Result<()> {
+        log::info!(
+            "setting retention policy on log group '{log_group_name}' to {retention_in_days} day(s), region '{}'",
+            self.region
+        );
+        self.logs_cli
+            .put_retention_policy()
+            .log_group_name(log_group_name)
+            .retention_in_days(retention_in_days)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed put_retention_policy {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        log::info!("applied retention policy");
+        Ok(())
+    }
+
+    /// Creates (or updates) a metric filter that turns matching log events
+    /// into a CloudWatch metric. Idempotent: CloudWatch Logs upserts a
+    /// metric filter by name, so calling this again with the same
+    /// `filter_name` just overwrites it.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutMetricFilter.html
+    pub async fn put_metric_filter(
+        &self,
+        log_group_name: &str,
+        filter_name: &str,
+        filter_pattern: &str,
+        metric_transformation: MetricTransformation,
+    ) -> Result<()> {
+        log::info!(
+            "putting metric filter '{filter_name}' on log group '{log_group_name}', region '{}'",
+            self.region
+        );
+        self.logs_cli
+            .put_metric_filter()
+            .log_group_name(log_group_name)
+            .filter_name(filter_name)
+            .filter_pattern(filter_pattern)
+            .metric_transformations(metric_transformation)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed put_metric_filter {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        log::info!("put metric filter");
+        Ok(())
+    }
+
+    /// Creates (or updates) a subscription filter that forwards matching
+    /// log events to `destination_arn` (e.g., a Kinesis stream or Lambda
+    /// function). Idempotent for the same reason as `put_metric_filter`:
+    /// CloudWatch Logs upserts by `filter_name`.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutSubscriptionFilter.html
+    pub async fn put_subscription_filter(
+        &self,
+        log_group_name: &str,
+        filter_name: &str,
+        filter_pattern: &str,
+        destination_arn: &str,
+    ) -> Result<()> {
+        log::info!(
+            "putting subscription filter '{filter_name}' on log group '{log_group_name}' to '{destination_arn}', region '{}'",
+            self.region
+        );
+        self.logs_cli
+            .put_subscription_filter()
+            .log_group_name(log_group_name)
+            .filter_name(filter_name)
+            .filter_pattern(filter_pattern)
+            .destination_arn(destination_arn)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed put_subscription_filter {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        log::info!("put subscription filter");
         Ok(())
     }
 
@@ -193,6 +501,447 @@ impl Manager {
         };
         Ok(())
     }
+
+    /// Creates a CloudWatch log stream within a log group.
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_CreateLogStream.html
+    pub async fn create_log_stream(&self, log_group_name: &str, log_stream_name: &str) -> Result<()> {
+        log::info!(
+            "creating CloudWatch log stream '{log_stream_name}' in log group '{log_group_name}', region '{}'",
+            self.region
+        );
+        let ret = self
+            .logs_cli
+            .create_log_stream()
+            .log_group_name(log_group_name)
+            .log_stream_name(log_stream_name)
+            .send()
+            .await;
+        match ret {
+            Ok(_) => {
+                log::info!("created CloudWatch log stream");
+            }
+            Err(e) => {
+                if is_err_already_exists_create_log_stream(&e) {
+                    log::warn!("log stream already exists ({})", e);
+                } else {
+                    return Err(Error::API {
+                        message: format!("failed create_log_stream {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ships log events to a log stream, creating it first if missing.
+    ///
+    /// Events are sorted by timestamp and split into batches honoring the
+    /// `PutLogEvents` service limits (10,000 events, 1 MiB per call). The
+    /// `sequenceToken` returned by each call is cached and resupplied on the
+    /// next one; on `InvalidSequenceTokenException`/`DataAlreadyAcceptedException`
+    /// the expected token is read out of the error and the call is retried.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutLogEvents.html
+    pub async fn put_log_events(
+        &self,
+        log_group_name: &str,
+        log_stream_name: &str,
+        mut events: Vec<InputLogEvent>,
+    ) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        events.sort_by_key(|e| e.timestamp.unwrap_or(0));
+
+        log::info!(
+            "posting {} log events to '{log_group_name}/{log_stream_name}', region '{}'",
+            events.len(),
+            self.region
+        );
+        for batch in batch_log_events(events) {
+            self.put_log_events_batch(log_group_name, log_stream_name, batch)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn put_log_events_batch(
+        &self,
+        log_group_name: &str,
+        log_stream_name: &str,
+        events: Vec<InputLogEvent>,
+    ) -> Result<()> {
+        let key = (log_group_name.to_string(), log_stream_name.to_string());
+
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+
+            let token = {
+                let tokens = self.log_stream_tokens.lock().await;
+                tokens.get(&key).cloned()
+            };
+            let mut req = self
+                .logs_cli
+                .put_log_events()
+                .log_group_name(log_group_name)
+                .log_stream_name(log_stream_name)
+                .set_log_events(Some(events.clone()));
+            if let Some(token) = &token {
+                req = req.sequence_token(token);
+            }
+
+            let ret = req.send().await;
+            match ret {
+                Ok(out) => {
+                    if let Some(next_token) = out.next_sequence_token {
+                        let mut tokens = self.log_stream_tokens.lock().await;
+                        tokens.insert(key, next_token);
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    if let Some(expected) = expected_sequence_token_from_put_log_events_err(&e) {
+                        log::warn!("sequence token stale, retrying with expected token ({})", e);
+                        let mut tokens = self.log_stream_tokens.lock().await;
+                        tokens.insert(key.clone(), expected);
+                        if attempt < MAX_PUT_LOG_EVENTS_ATTEMPTS {
+                            continue;
+                        }
+                    }
+                    return Err(Error::API {
+                        message: format!("failed put_log_events {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Tails log events out of `log_group_name`, optionally scoped to
+    /// streams starting with `log_stream_name_prefix` and/or matching a
+    /// `FilterLogEvents` `filter_pattern`.
+    ///
+    /// In snapshot mode (`follow: false`) the stream pages through
+    /// `filter_log_events` once and ends. In follow mode (`follow: true`)
+    /// it keeps polling every `poll_interval` after draining the current
+    /// results, tracking the last-seen event timestamp (and the event ids
+    /// at that exact timestamp, to avoid re-yielding events on the
+    /// boundary) so it never re-delivers an event. Events are yielded in
+    /// timestamp order in bounded chunks of `TAIL_CHUNK_SIZE`; because the
+    /// stream only resumes polling once the consumer polls for the next
+    /// item, a slow consumer naturally throttles the poll rate.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_FilterLogEvents.html
+    pub fn tail_log_events<'a>(
+        &'a self,
+        log_group_name: &'a str,
+        log_stream_name_prefix: Option<&'a str>,
+        filter_pattern: Option<&'a str>,
+        follow: bool,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<Vec<FilteredLogEvent>>> + 'a {
+        async_stream::try_stream! {
+            let mut last_seen_timestamp: i64 = 0;
+            let mut seen_at_boundary: HashSet<String> = HashSet::new();
+
+            loop {
+                let mut next_token: Option<String> = None;
+                let mut chunk: Vec<FilteredLogEvent> = Vec::new();
+
+                loop {
+                    let mut req = self
+                        .logs_cli
+                        .filter_log_events()
+                        .log_group_name(log_group_name);
+                    if let Some(prefix) = log_stream_name_prefix {
+                        req = req.log_stream_name_prefix(prefix);
+                    }
+                    if let Some(pattern) = filter_pattern {
+                        req = req.filter_pattern(pattern);
+                    }
+                    if last_seen_timestamp > 0 {
+                        req = req.start_time(last_seen_timestamp);
+                    }
+                    if let Some(token) = &next_token {
+                        req = req.next_token(token);
+                    }
+
+                    let ret = req.send().await.map_err(|e| Error::API {
+                        message: format!("failed filter_log_events {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+
+                    for event in ret.events.unwrap_or_default() {
+                        let ts = event.timestamp.unwrap_or(0);
+                        if ts < last_seen_timestamp {
+                            continue;
+                        }
+                        let id = event.event_id.clone().unwrap_or_default();
+                        if ts == last_seen_timestamp {
+                            if !seen_at_boundary.insert(id) {
+                                continue;
+                            }
+                        } else {
+                            last_seen_timestamp = ts;
+                            seen_at_boundary.clear();
+                            seen_at_boundary.insert(id);
+                        }
+
+                        chunk.push(event);
+                        if chunk.len() >= TAIL_CHUNK_SIZE {
+                            yield std::mem::take(&mut chunk);
+                        }
+                    }
+
+                    next_token = ret.next_token;
+                    if next_token.is_none() {
+                        break;
+                    }
+                }
+
+                if !chunk.is_empty() {
+                    yield chunk;
+                }
+
+                if !follow {
+                    break;
+                }
+                sleep(poll_interval).await;
+            }
+        }
+    }
+
+    /// Starts a Logs Insights query over `log_groups` and returns its
+    /// query id.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_StartQuery.html
+    pub async fn start_query(
+        &self,
+        log_groups: Vec<String>,
+        query_string: &str,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<String> {
+        log::info!(
+            "starting Logs Insights query over {} log group(s), region '{}'",
+            log_groups.len(),
+            self.region
+        );
+        let ret = self
+            .logs_cli
+            .start_query()
+            .set_log_group_names(Some(log_groups))
+            .query_string(query_string)
+            .start_time(start_time)
+            .end_time(end_time)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed start_query {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        ret.query_id.ok_or_else(|| Error::Other {
+            message: "start_query returned no query_id".to_string(),
+            retryable: false,
+        })
+    }
+
+    /// Polls `get_query_results` for `query_id` until it reaches a
+    /// terminal status (`Complete`, `Failed`, or `Cancelled`), doubling
+    /// the poll interval up to `MAX_QUERY_POLL_INTERVAL` between polls.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_GetQueryResults.html
+    pub async fn get_query_results(&self, query_id: &str) -> Result<QueryResult> {
+        let mut interval = INITIAL_QUERY_POLL_INTERVAL;
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+
+            let ret = self
+                .logs_cli
+                .get_query_results()
+                .query_id(query_id)
+                .send()
+                .await
+                .map_err(|e| Error::API {
+                    message: format!("failed get_query_results {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+
+            let terminal = matches!(
+                ret.status,
+                Some(QueryStatus::Complete) | Some(QueryStatus::Failed) | Some(QueryStatus::Cancelled)
+            );
+            if terminal {
+                let rows = ret
+                    .results
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|row| {
+                        row.into_iter()
+                            .filter_map(|f| Some((f.field?, f.value.unwrap_or_default())))
+                            .collect::<HashMap<String, String>>()
+                    })
+                    .collect();
+                return Ok(QueryResult {
+                    status: ret
+                        .status
+                        .map(|s| s.as_str().to_string())
+                        .unwrap_or_default(),
+                    rows,
+                    bytes_scanned: ret.statistics.as_ref().and_then(|s| s.bytes_scanned),
+                    records_matched: ret.statistics.as_ref().and_then(|s| s.records_matched),
+                    records_scanned: ret.statistics.as_ref().and_then(|s| s.records_scanned),
+                });
+            }
+
+            if attempt >= MAX_QUERY_POLL_ATTEMPTS {
+                return Err(Error::Other {
+                    message: format!(
+                        "query '{query_id}' did not reach a terminal status after {attempt} polls"
+                    ),
+                    retryable: true,
+                });
+            }
+            sleep(interval).await;
+            interval = std::cmp::min(interval * 2, MAX_QUERY_POLL_INTERVAL);
+        }
+    }
+
+    /// Cancels a running Logs Insights query. Succeeds silently if the
+    /// query has already finished or does not exist.
+    ///
+    /// ref. https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_StopQuery.html
+    pub async fn stop_query(&self, query_id: &str) -> Result<()> {
+        let ret = self.logs_cli.stop_query().query_id(query_id).send().await;
+        if let Err(e) = ret {
+            if !is_err_already_stopped_stop_query(&e) {
+                return Err(Error::API {
+                    message: format!("failed stop_query {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                });
+            }
+            log::warn!("stop_query failed; query already finished or unknown ({})", e);
+        }
+        Ok(())
+    }
+
+    /// Starts a Logs Insights query and polls it to completion, honoring
+    /// `timeout` as an overall deadline. If the deadline is hit, the query
+    /// is cancelled via `stop_query` before returning the timeout error.
+    pub async fn run_query(
+        &self,
+        log_groups: Vec<String>,
+        query_string: &str,
+        start_time: i64,
+        end_time: i64,
+        timeout: Duration,
+    ) -> Result<QueryResult> {
+        let query_id = self
+            .start_query(log_groups, query_string, start_time, end_time)
+            .await?;
+        match tokio::time::timeout(timeout, self.get_query_results(&query_id)).await {
+            Ok(result) => result,
+            Err(_elapsed) => {
+                log::warn!("query '{query_id}' timed out after {:?}, stopping it", timeout);
+                let _ = self.stop_query(&query_id).await;
+                Err(Error::Other {
+                    message: format!("query '{query_id}' timed out after {:?}", timeout),
+                    retryable: true,
+                })
+            }
+        }
+    }
+}
+
+/// Result of a completed (or failed/cancelled) Logs Insights query.
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub status: String,
+    pub rows: Vec<HashMap<String, String>>,
+    pub bytes_scanned: Option<f64>,
+    pub records_matched: Option<f64>,
+    pub records_scanned: Option<f64>,
+}
+
+/// Splits events (already sorted by timestamp) into batches that each honor
+/// the `PutLogEvents` event-count and byte-size service limits.
+fn batch_log_events(events: Vec<InputLogEvent>) -> Vec<Vec<InputLogEvent>> {
+    let mut batches = Vec::new();
+    let mut current: Vec<InputLogEvent> = Vec::new();
+    let mut current_bytes: usize = 0;
+
+    for event in events {
+        let event_bytes = event.message.as_ref().map(|m| m.len()).unwrap_or(0) + LOG_EVENT_OVERHEAD_BYTES;
+        let would_overflow = current.len() + 1 > MAX_LOG_EVENTS_PER_BATCH
+            || current_bytes + event_bytes > MAX_LOG_EVENTS_BYTES_PER_BATCH;
+        if would_overflow && !current.is_empty() {
+            batches.push(current);
+            current = Vec::new();
+            current_bytes = 0;
+        }
+        current_bytes += event_bytes;
+        current.push(event);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+/// Matches `${VAR}` or `${VAR:-default}` placeholders in a config
+/// template; capture group 1 is the variable name, group 3 (if present)
+/// is the default.
+fn placeholder_regex() -> &'static Regex {
+    static RE: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(:-([^}]*))?\}")
+            .expect("placeholder regex is valid")
+    })
+}
+
+/// Placeholder for an escaped `$${` sequence while the substitution
+/// regex runs, so an escaped placeholder is never itself substituted.
+const ESCAPED_DOLLAR_SENTINEL: &str = "\u{0}__cw_config_escaped_dollar__\u{0}";
+
+/// Substitutes `${VAR}` / `${VAR:-default}` placeholders in `input`,
+/// resolving each from `overrides` first, then the process environment,
+/// then its `:-default` if present. `$${...}` is an escape for a literal
+/// `${...}`. Collects every placeholder that could not be resolved and
+/// fails the whole substitution if any remain, rather than partially
+/// rendering the template.
+fn render_config_template(input: &str, overrides: &HashMap<String, String>) -> io::Result<String> {
+    let escaped = input.replace("$${", &format!("{ESCAPED_DOLLAR_SENTINEL}{{"));
+
+    let mut missing: Vec<String> = Vec::new();
+    let rendered = placeholder_regex().replace_all(&escaped, |caps: &regex::Captures| {
+        let name = &caps[1];
+        if let Some(v) = overrides.get(name) {
+            return v.clone();
+        }
+        if let Ok(v) = std::env::var(name) {
+            return v;
+        }
+        if let Some(default) = caps.get(3) {
+            return default.as_str().to_string();
+        }
+        missing.push(name.to_string());
+        String::new()
+    });
+
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unresolved required config placeholder(s): {}",
+                missing.join(", ")
+            ),
+        ));
+    }
+
+    Ok(rendered.replace(&format!("{ESCAPED_DOLLAR_SENTINEL}{{"), "${"))
 }
 
 #[inline]
@@ -233,6 +982,44 @@ fn is_err_does_not_exist_delete_log_group(
     }
 }
 
+#[inline]
+fn is_err_already_exists_create_log_stream(
+    e: &SdkError<CreateLogStreamError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().is_resource_already_exists_exception(),
+        _ => false,
+    }
+}
+
+#[inline]
+fn is_err_already_stopped_stop_query(
+    e: &SdkError<StopQueryError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => {
+            matches!(err.err(), StopQueryError::ResourceNotFoundException(_))
+        }
+        _ => false,
+    }
+}
+
+/// Extracts the `expectedSequenceToken` carried by `InvalidSequenceTokenException`
+/// or `DataAlreadyAcceptedException`, if any.
+#[inline]
+fn expected_sequence_token_from_put_log_events_err(
+    e: &SdkError<PutLogEventsError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> Option<String> {
+    match e {
+        SdkError::ServiceError(err) => match err.err() {
+            PutLogEventsError::InvalidSequenceTokenException(e) => e.expected_sequence_token.clone(),
+            PutLogEventsError::DataAlreadyAcceptedException(e) => e.expected_sequence_token.clone(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// ref. https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch-Agent-Configuration-File-Details.html
 pub const DEFAULT_CONFIG_FILE_PATH: &str = "/opt/aws/amazon-cloudwatch-agent/bin/config.json";
 
@@ -725,11 +1512,165 @@ impl Config {
         })
     }
 
-    /// Validates the configuration.
+    /// Loads a config file that may contain `${VAR}` / `${VAR:-default}`
+    /// placeholders, substituting them from `overrides` (checked first),
+    /// then the process environment, then the `:-default` if present,
+    /// before deserializing. A literal `${...}` can be emitted with the
+    /// `$${...}` escape. Fails listing every placeholder that could not be
+    /// resolved, rather than just the first.
+    pub fn load_templated(file_path: &str, overrides: &HashMap<String, String>) -> io::Result<Self> {
+        log::info!("loading templated CloudWatch config from {}", file_path);
+
+        if !Path::new(file_path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("file {} does not exists", file_path),
+            ));
+        }
+
+        let raw = fs::read_to_string(file_path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("failed to read {} ({})", file_path, e),
+            )
+        })?;
+        let rendered = render_config_template(&raw, overrides)?;
+        serde_json::from_str(&rendered).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid JSON after templating: {}", e),
+            )
+        })
+    }
+
+    /// Validates the configuration against the embedded CloudWatch agent
+    /// JSON Schema (required fields, enum values, `log_group_name`/
+    /// `log_stream_name` patterns, etc.), collecting every failing
+    /// instance path rather than stopping at the first one.
     pub fn validate(&self) -> io::Result<()> {
         log::info!("validating the CloudWatch configuration");
 
-        Ok(())
+        let value = serde_json::to_value(self).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("failed to serialize Config for validation ({e})"),
+            )
+        })?;
+        let errors = config_schema::validate_config(&value);
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let message = errors
+            .iter()
+            .map(|(pointer, msg)| format!("{}: {}", if pointer.is_empty() { "/" } else { pointer }, msg))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("CloudWatch config failed validation: {message}"),
+        ))
+    }
+
+    /// Reports whether `self` and `other` describe the same effective
+    /// configuration, ignoring the order of order-insensitive arrays
+    /// (e.g. `measurement`/`resources`/`collect_list` entries). Useful to
+    /// skip an agent restart when a freshly-read config hasn't
+    /// meaningfully drifted from what's already applied.
+    pub fn semantically_equals(&self, other: &Config) -> bool {
+        self.diff(other).is_empty()
+    }
+
+    /// Returns the JSON pointers that differ between `self` and `other`
+    /// after order-insensitive-array normalization, or an empty `Vec` if
+    /// they're semantically equal.
+    pub fn diff(&self, other: &Config) -> Vec<String> {
+        let a = canonicalize_config_value(
+            &serde_json::to_value(self).unwrap_or(serde_json::Value::Null),
+            "",
+        );
+        let b = canonicalize_config_value(
+            &serde_json::to_value(other).unwrap_or(serde_json::Value::Null),
+            "",
+        );
+        let mut pointers = Vec::new();
+        diff_values(&a, &b, "", &mut pointers);
+        pointers
+    }
+}
+
+/// JSON pointer suffixes whose array elements are order-insensitive, so
+/// they're sorted (by canonical serialized form) before comparison.
+const ORDER_INSENSITIVE_ARRAY_SUFFIXES: &[&str] =
+    &["/measurement", "/resources", "/collect_list", "/aggregation_dimensions"];
+
+fn is_order_insensitive_array(pointer: &str) -> bool {
+    ORDER_INSENSITIVE_ARRAY_SUFFIXES
+        .iter()
+        .any(|suffix| pointer.ends_with(suffix))
+}
+
+/// Recursively normalizes a serialized `Config`: object keys are sorted
+/// (via `BTreeMap`, same as `serde_json::Value`'s default object
+/// representation), and designated order-insensitive arrays have their
+/// (already-normalized) elements sorted by canonical serialized form.
+fn canonicalize_config_value(value: &serde_json::Value, pointer: &str) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut sorted: std::collections::BTreeMap<String, serde_json::Value> =
+                std::collections::BTreeMap::new();
+            for (k, v) in map {
+                sorted.insert(
+                    k.clone(),
+                    canonicalize_config_value(v, &format!("{pointer}/{k}")),
+                );
+            }
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+        serde_json::Value::Array(arr) => {
+            let mut items: Vec<serde_json::Value> = arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| canonicalize_config_value(v, &format!("{pointer}/{i}")))
+                .collect();
+            if is_order_insensitive_array(pointer) {
+                items.sort_by_key(|v| v.to_string());
+            }
+            serde_json::Value::Array(items)
+        }
+        other => other.clone(),
+    }
+}
+
+/// Collects the JSON pointers at which two already-canonicalized values
+/// differ.
+fn diff_values(a: &serde_json::Value, b: &serde_json::Value, pointer: &str, out: &mut Vec<String>) {
+    match (a, b) {
+        (serde_json::Value::Object(ao), serde_json::Value::Object(bo)) => {
+            let mut keys: std::collections::BTreeSet<&String> = ao.keys().collect();
+            keys.extend(bo.keys());
+            for k in keys {
+                let child_pointer = format!("{pointer}/{k}");
+                match (ao.get(k), bo.get(k)) {
+                    (Some(av), Some(bv)) => diff_values(av, bv, &child_pointer, out),
+                    _ => out.push(child_pointer),
+                }
+            }
+        }
+        (serde_json::Value::Array(aa), serde_json::Value::Array(ba)) => {
+            if aa.len() != ba.len() {
+                out.push(if pointer.is_empty() { "/".to_string() } else { pointer.to_string() });
+                return;
+            }
+            for (i, (av, bv)) in aa.iter().zip(ba.iter()).enumerate() {
+                diff_values(av, bv, &format!("{pointer}/{i}"), out);
+            }
+        }
+        _ => {
+            if a != b {
+                out.push(if pointer.is_empty() { "/".to_string() } else { pointer.to_string() });
+            }
+        }
     }
 }
 
@@ -749,3 +1690,61 @@ fn test_config() {
     assert!(ret.is_ok());
     fs::remove_file(p).unwrap();
 }
+
+#[test]
+fn test_validate_known_good_and_bad_config() {
+    let good = Config::default();
+    assert!(good.validate().is_ok());
+
+    let mut bad = Config::default();
+    bad.agent.as_mut().unwrap().metrics_collection_interval = 0;
+    let message = bad.validate().unwrap_err().to_string();
+    assert!(
+        message.contains("/agent/metrics_collection_interval"),
+        "expected the minimum-violation pointer in: {message}"
+    );
+}
+
+#[test]
+fn test_render_config_template() {
+    let mut overrides = HashMap::new();
+    overrides.insert("REGION".to_string(), "us-west-2".to_string());
+
+    let rendered = render_config_template(
+        r#"{"region":"${REGION}","x":"${MISSING:-fallback}"}"#,
+        &overrides,
+    )
+    .unwrap();
+    assert_eq!(rendered, r#"{"region":"us-west-2","x":"fallback"}"#);
+
+    let err = render_config_template(r#"{"y":"${TOTALLY_UNSET}"}"#, &overrides).unwrap_err();
+    assert!(err.to_string().contains("TOTALLY_UNSET"));
+}
+
+#[test]
+fn test_diff_reports_real_difference() {
+    let a = Config::default();
+    let mut b = Config::default();
+    b.agent.as_mut().unwrap().logfile = "/tmp/other.log".to_string();
+
+    assert!(!a.semantically_equals(&b));
+    assert_eq!(a.diff(&b), vec!["/agent/logfile".to_string()]);
+}
+
+#[test]
+fn test_diff_ignores_order_insensitive_array_order() {
+    let mut a = Config::new();
+    let mut b = Config::new();
+    a.metrics = Some(Metrics::new(30));
+    b.metrics = Some(Metrics::new(30));
+    b.metrics
+        .as_mut()
+        .unwrap()
+        .aggregation_dimensions
+        .as_mut()
+        .unwrap()
+        .reverse();
+
+    assert!(a.semantically_equals(&b));
+    assert_eq!(a.diff(&b), Vec::<String>::new());
+}
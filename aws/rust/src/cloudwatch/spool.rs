@@ -0,0 +1,271 @@
+//! Durable on-disk spool for CloudWatch metric submissions, so
+//! `Manager::put_metric_data_durable` survives process restarts and
+//! transient network outages.
+//!
+//! Each enqueued chunk is serialized to its own file in the spool
+//! directory, tagged with an idempotency key built from a process-start
+//! nonce, a monotonic counter, and the enqueue timestamp, so a chunk is
+//! written exactly once and concurrent writers never collide on a file
+//! name. The directory itself is the queue: there is no separate
+//! in-memory backlog, so a fresh `MetricSpool::new` pointed at an
+//! existing directory picks up whatever a previous process left behind
+//! without any extra "reload" step.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use aws_sdk_cloudwatch::types::{Dimension, MetricDatum, StandardUnit};
+use aws_smithy_types::DateTime;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::errors::{Error, Result};
+
+/// Backoff applied after the first retryable failure of a spooled chunk.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound on the backoff applied between retries of a single
+/// spooled chunk.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Serializable mirror of `aws_sdk_cloudwatch::types::MetricDatum`, since
+/// the SDK type itself does not implement `serde::Serialize`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SpooledDatum {
+    metric_name: Option<String>,
+    value: Option<f64>,
+    unit: Option<String>,
+    timestamp_epoch_seconds: Option<i64>,
+    dimensions: Vec<(String, String)>,
+}
+
+impl From<&MetricDatum> for SpooledDatum {
+    fn from(d: &MetricDatum) -> Self {
+        Self {
+            metric_name: d.metric_name.clone(),
+            value: d.value,
+            unit: d.unit.as_ref().map(|u| u.as_str().to_string()),
+            timestamp_epoch_seconds: d.timestamp.map(|t| t.secs()),
+            dimensions: d
+                .dimensions
+                .as_ref()
+                .map(|ds| {
+                    ds.iter()
+                        .filter_map(|dim| Some((dim.name.clone()?, dim.value.clone()?)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl SpooledDatum {
+    fn into_metric_datum(self) -> MetricDatum {
+        let mut b = MetricDatum::builder();
+        if let Some(name) = self.metric_name {
+            b = b.metric_name(name);
+        }
+        if let Some(value) = self.value {
+            b = b.value(value);
+        }
+        if let Some(unit) = self.unit {
+            b = b.unit(StandardUnit::from(unit.as_str()));
+        }
+        if let Some(secs) = self.timestamp_epoch_seconds {
+            b = b.timestamp(DateTime::from_secs(secs));
+        }
+        for (name, value) in self.dimensions {
+            b = b.dimensions(Dimension::builder().name(name).value(value).build());
+        }
+        b.build()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SpooledChunk {
+    idempotency_key: String,
+    namespace: String,
+    data: Vec<SpooledDatum>,
+}
+
+/// Durable on-disk spool of pending `PutMetricData` chunks.
+#[derive(Debug, Clone)]
+pub struct MetricSpool {
+    dir: PathBuf,
+    nonce: String,
+    counter: Arc<AtomicU64>,
+    /// Per-file retry state: the absolute deadline before which a file
+    /// should not be retried yet, and the backoff duration that produced
+    /// it (doubled on the next failure).
+    backoffs: Arc<Mutex<HashMap<PathBuf, (Instant, Duration)>>>,
+}
+
+impl MetricSpool {
+    /// Opens (creating if needed) the spool directory and picks a fresh
+    /// process-start nonce for idempotency keys.
+    pub fn new(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir).map_err(|e| Error::Other {
+            message: format!("failed to create metric spool dir '{dir}' ({e})"),
+            retryable: false,
+        })?;
+        let nonce = format!(
+            "{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0)
+        );
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            nonce,
+            counter: Arc::new(AtomicU64::new(0)),
+            backoffs: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Serializes one chunk to a new file in the spool directory, tagged
+    /// with a unique idempotency key so it is written (and later flushed)
+    /// exactly once.
+    pub fn enqueue(&self, namespace: &str, data: Vec<MetricDatum>) -> Result<()> {
+        let key = self.next_idempotency_key();
+        let chunk = SpooledChunk {
+            idempotency_key: key.clone(),
+            namespace: namespace.to_string(),
+            data: data.iter().map(SpooledDatum::from).collect(),
+        };
+        let path = self.dir.join(format!("{key}.json"));
+        let encoded = serde_json::to_vec(&chunk).map_err(|e| Error::Other {
+            message: format!("failed to serialize spooled metric chunk ({e})"),
+            retryable: false,
+        })?;
+        fs::write(&path, encoded).map_err(|e| Error::Other {
+            message: format!("failed to write spool file '{}' ({e})", path.display()),
+            retryable: false,
+        })?;
+        Ok(())
+    }
+
+    /// Returns the number of chunks currently pending on disk.
+    pub fn pending_count(&self) -> usize {
+        list_spool_files(&self.dir).len()
+    }
+
+    /// Drains every pending chunk in the spool directory once, calling
+    /// `send` (typically `Manager::put_metric_data`) for each. A chunk
+    /// whose send fails with a retryable error is left in place with its
+    /// own per-chunk exponential backoff; a non-retryable error is logged
+    /// and the chunk is dropped so a permanently-bad payload can't wedge
+    /// the spool.
+    ///
+    /// A file whose backoff deadline hasn't arrived yet is skipped, not
+    /// slept on, so one backed-off chunk can't stall delivery of every
+    /// other file in this pass (including this method's synchronous
+    /// caller, `Manager::flush`).
+    pub async fn drain_once<F, Fut>(&self, mut send: F)
+    where
+        F: FnMut(String, Vec<MetricDatum>) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        for path in list_spool_files(&self.dir) {
+            let deadline = {
+                let backoffs = self.backoffs.lock().await;
+                backoffs.get(&path).map(|(deadline, _)| *deadline)
+            };
+            if let Some(deadline) = deadline {
+                if deadline > Instant::now() {
+                    continue;
+                }
+            }
+
+            let bytes = match fs::read(&path) {
+                Ok(b) => b,
+                Err(e) => {
+                    log::warn!(
+                        "failed to read spool file '{}' ({e}), skipping this pass",
+                        path.display()
+                    );
+                    continue;
+                }
+            };
+            let chunk: SpooledChunk = match serde_json::from_slice(&bytes) {
+                Ok(c) => c,
+                Err(e) => {
+                    log::warn!(
+                        "spool file '{}' has invalid contents ({e}), dropping it",
+                        path.display()
+                    );
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+            };
+
+            let data: Vec<MetricDatum> = chunk
+                .data
+                .into_iter()
+                .map(SpooledDatum::into_metric_datum)
+                .collect();
+            match send(chunk.namespace, data).await {
+                Ok(()) => {
+                    let _ = fs::remove_file(&path);
+                    let mut backoffs = self.backoffs.lock().await;
+                    backoffs.remove(&path);
+                }
+                Err(e) if e.retryable() => {
+                    log::warn!(
+                        "retryable error flushing spool file '{}' ({e}), leaving it for next pass",
+                        path.display()
+                    );
+                    let mut backoffs = self.backoffs.lock().await;
+                    let next_backoff = backoffs
+                        .get(&path)
+                        .map(|(_, d)| std::cmp::min(*d * 2, MAX_BACKOFF))
+                        .unwrap_or(INITIAL_BACKOFF);
+                    backoffs.insert(path.clone(), (Instant::now() + next_backoff, next_backoff));
+                }
+                Err(e) => {
+                    log::warn!(
+                        "non-retryable error flushing spool file '{}' ({e}), dropping it",
+                        path.display()
+                    );
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+    }
+
+    fn next_idempotency_key(&self) -> String {
+        let seq = self.counter.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        format!("{}-{}-{}", self.nonce, seq, now)
+    }
+}
+
+/// Lists spool files sorted by name (idempotency keys are monotonic), so
+/// chunks are retried in roughly the order they were enqueued.
+fn list_spool_files(dir: &std::path::Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|rd| {
+            rd.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|p| p.extension().map(|ext| ext == "json").unwrap_or(false))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
@@ -0,0 +1,291 @@
+//! A [`log::Log`] implementation (and `tracing_subscriber::Layer`) that
+//! ships records straight to CloudWatch Logs, for processes that want
+//! their own logs to land in CloudWatch without installing and running
+//! the CloudWatch agent.
+//!
+//! Records are buffered on a background Tokio task and flushed on a
+//! timer or once the buffer approaches the `PutLogEvents` size limits;
+//! the actual send (sorting, batching, and `sequenceToken` tracking with
+//! retry on a stale token) is delegated to `Manager::put_log_events`.
+
+use aws_sdk_cloudwatchlogs::types::InputLogEvent;
+use aws_types::SdkConfig as AwsSdkConfig;
+use tokio::{
+    sync::mpsc,
+    time::{interval, sleep, Duration},
+};
+
+use crate::errors::Result;
+
+use super::{Manager, MAX_LOG_EVENTS_BYTES_PER_BATCH, MAX_LOG_EVENTS_PER_BATCH};
+
+/// CloudWatch Logs rejects events older than 14 days.
+const MAX_EVENT_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// CloudWatch Logs rejects events more than 2 hours in the future.
+const MAX_EVENT_SKEW_INTO_FUTURE: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// How many times `flush` retries a batch against throttling/timeout
+/// errors before giving up on retrying inline (matches
+/// `cloudwatch::Manager`'s own `MAX_PUT_LOG_EVENTS_ATTEMPTS`).
+const MAX_FLUSH_ATTEMPTS: u32 = 5;
+
+/// Base delay for the exponential backoff between flush retries.
+const FLUSH_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Upper bound on the backoff delay, so a sustained outage doesn't stall
+/// flushing for minutes at a time.
+const FLUSH_RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Ships buffered log records to a single CloudWatch Logs group/stream.
+///
+/// Implements `log::Log` directly; for `tracing` users, `CloudWatchLogsLayer`
+/// wraps a clone of this type as a `tracing_subscriber::Layer`.
+#[derive(Clone)]
+pub struct CloudWatchLogsWriter {
+    tx: mpsc::UnboundedSender<InputLogEvent>,
+}
+
+impl CloudWatchLogsWriter {
+    /// Creates the log group/stream if missing, then spawns the
+    /// background flush task. `flush_interval` bounds the maximum delay
+    /// before a buffered record is sent even if the batch size/count
+    /// limits are never hit.
+    pub async fn new(
+        shared_config: &AwsSdkConfig,
+        log_group_name: &str,
+        log_stream_name: &str,
+        flush_interval: Duration,
+    ) -> Result<Self> {
+        let manager = Manager::new(shared_config);
+        manager.create_log_group(log_group_name, None).await?;
+        manager
+            .create_log_stream(log_group_name, log_stream_name)
+            .await?;
+
+        let (tx, rx) = mpsc::unbounded_channel::<InputLogEvent>();
+        tokio::spawn(flush_loop(
+            manager,
+            log_group_name.to_string(),
+            log_stream_name.to_string(),
+            flush_interval,
+            rx,
+        ));
+
+        Ok(Self { tx })
+    }
+
+    /// Enqueues one message at the current time, clamped to the
+    /// `PutLogEvents` allowed timestamp window (dropped if too old,
+    /// clamped to "now" if too far in the future).
+    fn enqueue(&self, message: String) {
+        let Some(timestamp_ms) = clamped_timestamp_ms() else {
+            return;
+        };
+        let event = match InputLogEvent::builder()
+            .timestamp(timestamp_ms)
+            .message(message)
+            .build()
+        {
+            Ok(e) => e,
+            Err(e) => {
+                log::warn!("failed to build InputLogEvent ({e}), dropping record");
+                return;
+            }
+        };
+        // The receiver only disconnects once the writer (and every clone
+        // of it) has been dropped, at which point there's nowhere left
+        // to log this failure usefully, so it's silently ignored.
+        let _ = self.tx.send(event);
+    }
+
+    /// Wraps this writer as a `tracing_subscriber::Layer`.
+    pub fn into_tracing_layer(self) -> CloudWatchLogsLayer {
+        CloudWatchLogsLayer { writer: self }
+    }
+}
+
+impl log::Log for CloudWatchLogsWriter {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.enqueue(format!(
+            "{} {} {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+/// `tracing_subscriber::Layer` wrapper around `CloudWatchLogsWriter`.
+pub struct CloudWatchLogsLayer {
+    writer: CloudWatchLogsWriter,
+}
+
+impl<S> tracing_subscriber::Layer<S> for CloudWatchLogsLayer
+where
+    S: tracing::Subscriber,
+{
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut message = String::new();
+        event.record(&mut MessageVisitor(&mut message));
+        self.writer.enqueue(format!(
+            "{} {} {}",
+            event.metadata().level(),
+            event.metadata().target(),
+            message
+        ));
+    }
+}
+
+struct MessageVisitor<'a>(&'a mut String);
+
+impl tracing::field::Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            *self.0 = format!("{value:?}");
+        } else if self.0.is_empty() {
+            *self.0 = format!("{}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Drains the channel, flushing the accumulated buffer to CloudWatch
+/// Logs whenever `flush_interval` elapses, the buffer approaches the
+/// `PutLogEvents` size limits, or the channel closes (writer dropped).
+async fn flush_loop(
+    manager: Manager,
+    log_group_name: String,
+    log_stream_name: String,
+    flush_interval: Duration,
+    mut rx: mpsc::UnboundedReceiver<InputLogEvent>,
+) {
+    let mut buffer: Vec<InputLogEvent> = Vec::new();
+    let mut buffered_bytes: usize = 0;
+    let mut ticker = interval(flush_interval);
+
+    loop {
+        tokio::select! {
+            maybe_event = rx.recv() => {
+                match maybe_event {
+                    Some(event) => {
+                        buffered_bytes += event_size(&event);
+                        buffer.push(event);
+                        if buffer.len() >= MAX_LOG_EVENTS_PER_BATCH
+                            || buffered_bytes >= MAX_LOG_EVENTS_BYTES_PER_BATCH
+                        {
+                            flush(&manager, &log_group_name, &log_stream_name, &mut buffer).await;
+                            buffered_bytes = 0;
+                        }
+                    }
+                    None => {
+                        flush(&manager, &log_group_name, &log_stream_name, &mut buffer).await;
+                        return;
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                flush(&manager, &log_group_name, &log_stream_name, &mut buffer).await;
+                buffered_bytes = 0;
+            }
+        }
+    }
+}
+
+async fn flush(
+    manager: &Manager,
+    log_group_name: &str,
+    log_stream_name: &str,
+    buffer: &mut Vec<InputLogEvent>,
+) {
+    if buffer.is_empty() {
+        return;
+    }
+    let events = std::mem::take(buffer);
+
+    let mut attempt: u32 = 0;
+    loop {
+        attempt += 1;
+        // `Manager::put_log_events` already sorts by timestamp, batches to
+        // the service limits, and handles sequence-token retries.
+        match manager
+            .put_log_events(log_group_name, log_stream_name, events.clone())
+            .await
+        {
+            Ok(()) => return,
+            Err(e) => {
+                if !e.retryable() || attempt >= MAX_FLUSH_ATTEMPTS {
+                    log::warn!(
+                        "failed to ship buffered log events to CloudWatch Logs after {attempt} attempt(s), re-enqueuing for the next flush ({e})"
+                    );
+                    // Put the batch back rather than dropping it, so the
+                    // next flush cycle (next timer tick, or the next event
+                    // crossing a size threshold) gets another shot at it.
+                    buffer.extend(events);
+                    return;
+                }
+                let delay = backoff_with_jitter(attempt);
+                log::warn!(
+                    "failed to ship buffered log events to CloudWatch Logs, retrying in {delay:?} ({e})"
+                );
+                sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Exponential backoff (`FLUSH_RETRY_BASE_DELAY * 2^(attempt-1)`, capped
+/// at `FLUSH_RETRY_MAX_DELAY`) plus up to 20% jitter, so writers that hit
+/// the same throttling error don't all retry in lockstep.
+fn backoff_with_jitter(attempt: u32) -> Duration {
+    let shift = attempt.saturating_sub(1).min(8);
+    let base = (FLUSH_RETRY_BASE_DELAY * (1u32 << shift)).min(FLUSH_RETRY_MAX_DELAY);
+
+    let jitter_bound_ns = ((base.as_nanos() as u64) / 5).max(1);
+    let jitter_ns = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0)
+        % jitter_bound_ns;
+
+    base + Duration::from_nanos(jitter_ns)
+}
+
+fn event_size(event: &InputLogEvent) -> usize {
+    event.message.as_ref().map(|m| m.len()).unwrap_or(0) + super::LOG_EVENT_OVERHEAD_BYTES
+}
+
+/// Returns the current time in epoch milliseconds, or `None` if it falls
+/// outside the window CloudWatch Logs accepts (the event should be
+/// dropped), clamping a too-far-future timestamp back to "now".
+fn clamped_timestamp_ms() -> Option<i64> {
+    let now = std::time::SystemTime::now();
+    let now_ms = now
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+
+    let oldest_allowed_ms = now
+        .checked_sub(MAX_EVENT_AGE)
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let newest_allowed_ms = now_ms + MAX_EVENT_SKEW_INTO_FUTURE.as_millis() as i64;
+
+    if now_ms < oldest_allowed_ms {
+        return None;
+    }
+    Some(now_ms.min(newest_allowed_ms))
+}
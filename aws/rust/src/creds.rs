@@ -0,0 +1,83 @@
+//! Expiry-aware credential caching shared across clients, so STS
+//! `AssumeRole` and IMDS-derived credentials are fetched once and reused
+//! until near expiry instead of being re-resolved on every client
+//! construction.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use aws_credential_types::provider::{future, ProvideCredentials};
+use aws_credential_types::Credentials;
+use tokio::sync::Mutex;
+
+/// How long before the real expiration we stop serving a cached
+/// credential and force a re-fetch, so a request doesn't start using a
+/// credential that expires mid-flight.
+const DEFAULT_REFRESH_BUFFER: Duration = Duration::from_secs(5 * 60);
+
+/// Wraps an inner `ProvideCredentials` (e.g. the crate's `AssumeRole` or
+/// IMDS provider) so its credentials are fetched once and reused until
+/// `expiration - refresh_buffer`, re-fetching exactly once on expiry. A
+/// `tokio::sync::Mutex` held across the re-fetch `.await` doubles as the
+/// single-flight guard, so concurrent callers queue behind the first
+/// refresh instead of each hitting STS/IMDS themselves.
+#[derive(Clone)]
+pub struct CachedProvider {
+    inner: Arc<dyn ProvideCredentials>,
+    refresh_buffer: Duration,
+    cached: Arc<Mutex<Option<Credentials>>>,
+}
+
+impl CachedProvider {
+    /// Wraps `inner`, refreshing `DEFAULT_REFRESH_BUFFER` (5 minutes)
+    /// before expiration.
+    pub fn new(inner: impl ProvideCredentials + 'static) -> Self {
+        Self::with_refresh_buffer(inner, DEFAULT_REFRESH_BUFFER)
+    }
+
+    pub fn with_refresh_buffer(
+        inner: impl ProvideCredentials + 'static,
+        refresh_buffer: Duration,
+    ) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            refresh_buffer,
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn resolve(&self) -> aws_credential_types::provider::Result {
+        let mut cached = self.cached.lock().await;
+        if let Some(creds) = cached.as_ref() {
+            if !is_near_expiry(creds, self.refresh_buffer) {
+                return Ok(creds.clone());
+            }
+        }
+
+        let fresh = self.inner.provide_credentials().await?;
+        *cached = Some(fresh.clone());
+        Ok(fresh)
+    }
+}
+
+impl ProvideCredentials for CachedProvider {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(self.resolve())
+    }
+}
+
+/// Whether `now >= creds.expiry() - refresh_buffer`. Credentials with no
+/// expiration (e.g. long-lived static keys) are never considered near
+/// expiry.
+fn is_near_expiry(creds: &Credentials, refresh_buffer: Duration) -> bool {
+    match creds.expiry() {
+        Some(expiry) => match expiry.checked_sub(refresh_buffer) {
+            Some(cutoff) => SystemTime::now() >= cutoff,
+            None => true,
+        },
+        None => false,
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fmt, io};
+
+use aws_smithy_runtime_api::client::result::SdkError;
+
+/// Represents a manager-level error, wrapping the underlying AWS SDK error
+/// with a `retryable` hint so callers can decide whether to back off and retry.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed API request (including non-2xx responses from AWS).
+    API { message: String, retryable: bool },
+    /// Failed outside of an API request (e.g., local I/O, serialization).
+    Other { message: String, retryable: bool },
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Error::API { message, .. } => message.clone(),
+            Error::Other { message, .. } => message.clone(),
+        }
+    }
+
+    pub fn retryable(&self) -> bool {
+        match self {
+            Error::API { retryable, .. } => *retryable,
+            Error::Other { retryable, .. } => *retryable,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::API { message, retryable } => {
+                write!(f, "API error (retryable {}): {}", retryable, message)
+            }
+            Error::Other { message, retryable } => {
+                write!(f, "error (retryable {}): {}", retryable, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(io::ErrorKind::Other, e.message())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Determines whether the generic SDK error is retryable
+/// (e.g., timeouts, dispatch failures, or transient response errors).
+#[inline]
+pub fn is_sdk_err_retryable<E, R>(e: &SdkError<E, R>) -> bool {
+    match e {
+        SdkError::TimeoutError(_) | SdkError::ResponseError { .. } => true,
+        SdkError::DispatchFailure(e) => e.is_timeout() || e.is_io(),
+        _ => false,
+    }
+}
@@ -0,0 +1,42 @@
+pub mod creds;
+pub mod errors;
+pub mod observability;
+pub mod profiles;
+
+#[cfg(feature = "cloudwatch")]
+pub mod cloudwatch;
+#[cfg(feature = "s3")]
+pub mod s3;
+
+use aws_config::{meta::region::RegionProviderChain, BehaviorVersion};
+use aws_types::{region::Region, SdkConfig as AwsSdkConfig};
+use tokio::time::Duration;
+
+/// Loads the shared AWS SDK configuration, optionally pinned to a region,
+/// profile, and request timeout.
+///
+/// ref. <https://docs.rs/aws-config/latest/aws_config/fn.load_defaults.html>
+pub async fn load_config(
+    region: Option<String>,
+    profile_name: Option<String>,
+    timeout: Option<Duration>,
+) -> AwsSdkConfig {
+    let region_provider = match region {
+        Some(r) => RegionProviderChain::first_try(Region::new(r)).or_default_provider(),
+        None => RegionProviderChain::default_provider(),
+    };
+
+    let mut loader =
+        aws_config::defaults(BehaviorVersion::latest()).region(region_provider);
+    if let Some(profile_name) = &profile_name {
+        loader = loader.profile_name(profile_name);
+    }
+    if let Some(timeout) = timeout {
+        let timeout_config = aws_config::timeout::TimeoutConfig::builder()
+            .operation_timeout(timeout)
+            .build();
+        loader = loader.timeout_config(timeout_config);
+    }
+
+    loader.load().await
+}
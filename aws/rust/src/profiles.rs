@@ -0,0 +1,146 @@
+//! Multi-profile / multi-region enumeration and per-profile client
+//! construction, built on top of `load_config`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::errors::{Error, Result};
+use aws_types::SdkConfig as AwsSdkConfig;
+use futures::future::join_all;
+
+/// One profile found in `~/.aws/config` and/or `~/.aws/credentials`,
+/// with whatever default `region` its `config` section pins (if any).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub region: Option<String>,
+}
+
+/// The crate's service clients, constructed for one profile/region pair.
+pub struct Clients {
+    pub config: AwsSdkConfig,
+    #[cfg(feature = "s3")]
+    pub s3: crate::s3::Manager,
+    #[cfg(feature = "cloudwatch")]
+    pub cloudwatch: crate::cloudwatch::Manager,
+}
+
+/// Parses `~/.aws/config` and `~/.aws/credentials`, returning every
+/// profile name found in either file, annotated with the default region
+/// declared for it in `~/.aws/config` (if any).
+pub fn list_profiles() -> Result<Vec<Profile>> {
+    let home = home_dir()?;
+    let mut regions: HashMap<String, String> = HashMap::new();
+    let mut names: Vec<String> = Vec::new();
+
+    if let Some(contents) = read_optional(home.join(".aws").join("config"))? {
+        for (section, kvs) in parse_ini_sections(&contents) {
+            // `~/.aws/config` names non-default profiles "profile <name>";
+            // only the default profile's section is bare "default".
+            let name = section
+                .strip_prefix("profile ")
+                .unwrap_or(section.as_str())
+                .to_string();
+            if let Some(region) = kvs.get("region") {
+                regions.insert(name.clone(), region.clone());
+            }
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    if let Some(contents) = read_optional(home.join(".aws").join("credentials"))? {
+        for (name, _) in parse_ini_sections(&contents) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let region = regions.get(&name).cloned();
+            Profile { name, region }
+        })
+        .collect())
+}
+
+/// Loads the shared config for `profile`/`region`, then constructs the
+/// crate's feature-gated service clients bound to it.
+pub async fn clients_for(profile: &str, region: &str) -> Clients {
+    let config =
+        crate::load_config(Some(region.to_string()), Some(profile.to_string()), None).await;
+    Clients {
+        #[cfg(feature = "s3")]
+        s3: crate::s3::Manager::new(&config),
+        #[cfg(feature = "cloudwatch")]
+        cloudwatch: crate::cloudwatch::Manager::new(&config),
+        config,
+    }
+}
+
+/// Fans `f` out across `regions` concurrently (e.g. the sweeper, or an
+/// AMI search) and collects each region's result, paired with the
+/// region it came from.
+pub async fn for_each_region<F, Fut, T>(regions: &[String], f: F) -> Vec<(String, T)>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = T>,
+{
+    let futures = regions.iter().cloned().map(|region| {
+        let fut = f(region.clone());
+        async move { (region, fut.await) }
+    });
+    join_all(futures).await
+}
+
+fn home_dir() -> Result<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from).ok_or_else(|| Error::Other {
+        message: "HOME environment variable not set".to_string(),
+        retryable: false,
+    })
+}
+
+fn read_optional(path: PathBuf) -> Result<Option<String>> {
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::Other {
+            message: format!("failed to read '{}' ({})", path.display(), e),
+            retryable: false,
+        }),
+    }
+}
+
+/// A minimal INI parser covering what `~/.aws/config` and
+/// `~/.aws/credentials` actually use: `[section]` headers and `key =
+/// value` lines, with `#`/`;` comment lines ignored.
+fn parse_ini_sections(contents: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut sections = Vec::new();
+    let mut current: Option<(String, HashMap<String, String>)> = None;
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if let Some(section) = current.take() {
+                sections.push(section);
+            }
+            current = Some((name.trim().to_string(), HashMap::new()));
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if let Some((_, kvs)) = current.as_mut() {
+                kvs.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+    }
+    if let Some(section) = current.take() {
+        sections.push(section);
+    }
+    sections
+}
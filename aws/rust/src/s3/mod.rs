@@ -0,0 +1,1216 @@
+use std::{collections::HashMap, fs, fs::File, io::Read, path::Path, sync::Arc};
+
+use crate::errors::{self, Error, Result};
+use crate::observability::ApiMetrics;
+use aws_sdk_s3::{
+    config::Builder as S3ConfigBuilder,
+    operation::head_object::HeadObjectOutput,
+    presigning::PresigningConfig,
+    primitives::ByteStream,
+    types::{Bucket, CompletedMultipartUpload, CompletedPart, MetadataDirective, Object},
+    Client,
+};
+use aws_types::SdkConfig as AwsSdkConfig;
+use futures::Stream;
+use tokio::{
+    sync::Semaphore,
+    time::{sleep, Duration, Instant},
+};
+
+/// Minimum part size allowed by S3 for all but the last part of a multipart upload.
+/// ref. <https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html>
+pub const MIN_MULTIPART_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Default part size used by `put_object_multipart_with_retries` when the
+/// caller does not request a specific size.
+pub const DEFAULT_MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Bounds how many parts are uploaded concurrently for a single multipart upload.
+const DEFAULT_MULTIPART_CONCURRENCY: usize = 5;
+
+/// A single `CopyObject` request is capped at 5 GiB; larger sources must use
+/// the `UploadPartCopy` multipart flow.
+/// ref. <https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html>
+const MAX_SINGLE_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Implements AWS S3 manager.
+#[derive(Clone)]
+pub struct Manager {
+    pub region: String,
+    cli: Client,
+    metrics: ApiMetrics,
+}
+
+impl std::fmt::Debug for Manager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Manager").field("region", &self.region).finish()
+    }
+}
+
+impl Manager {
+    pub fn new(shared_config: &AwsSdkConfig) -> Self {
+        let cli = Client::new(shared_config);
+        Self {
+            region: shared_config.region().unwrap().to_string(),
+            cli,
+            metrics: ApiMetrics::new("aws_manager::s3"),
+        }
+    }
+
+    /// Creates a manager pointed at an S3-compatible endpoint (e.g., MinIO,
+    /// Garage, Ceph RGW) instead of real AWS S3.
+    ///
+    /// `force_path_style` must be `true` for servers that do not support
+    /// virtual-hosted-style addressing (`https://{bucket}.{endpoint}`), which
+    /// is the case for most self-hosted S3-compatible servers.
+    pub fn new_with_endpoint(
+        shared_config: &AwsSdkConfig,
+        endpoint_url: &str,
+        force_path_style: bool,
+    ) -> Self {
+        let s3_config = S3ConfigBuilder::from(shared_config)
+            .endpoint_url(endpoint_url)
+            .force_path_style(force_path_style)
+            .build();
+        let cli = Client::from_conf(s3_config);
+        Self {
+            region: shared_config.region().unwrap().to_string(),
+            cli,
+            metrics: ApiMetrics::new("aws_manager::s3"),
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        self.cli.clone()
+    }
+
+    /// Checks if the S3 bucket exists.
+    pub async fn bucket_exists(&self, s3_bucket: &str) -> Result<bool> {
+        self.metrics
+            .record("bucket_exists", s3_bucket, async {
+                let ret = self.cli.head_bucket().bucket(s3_bucket).send().await;
+                match ret {
+                    Ok(_) => Ok(true),
+                    Err(e) => {
+                        if is_err_does_not_exist_head_bucket(&e) {
+                            return Ok(false);
+                        }
+                        Err(Error::API {
+                            message: format!("failed head_bucket {:?}", e),
+                            retryable: errors::is_sdk_err_retryable(&e),
+                        })
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Creates a S3 bucket in the manager's region.
+    /// Succeeds silently (logging a warning) if the bucket already exists.
+    pub async fn create_bucket(&self, s3_bucket: &str) -> Result<()> {
+        log::info!(
+            "creating S3 bucket '{s3_bucket}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("create_bucket", s3_bucket, async {
+                let ret = self.cli.create_bucket().bucket(s3_bucket).send().await;
+                match ret {
+                    Ok(_) => {
+                        log::info!("successfully created bucket");
+                    }
+                    Err(e) => {
+                        if is_err_already_owned_create_bucket(&e) {
+                            log::warn!("bucket already exists and is owned by us ({})", e);
+                            return Ok(());
+                        }
+                        return Err(Error::API {
+                            message: format!("failed create_bucket {:?}", e),
+                            retryable: errors::is_sdk_err_retryable(&e),
+                        });
+                    }
+                };
+                Ok(())
+            })
+            .await
+    }
+
+    /// Deletes the S3 bucket. Succeeds silently if the bucket does not exist.
+    pub async fn delete_bucket(&self, s3_bucket: &str) -> Result<()> {
+        log::info!(
+            "deleting S3 bucket '{s3_bucket}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("delete_bucket", s3_bucket, async {
+                let ret = self.cli.delete_bucket().bucket(s3_bucket).send().await;
+                match ret {
+                    Ok(_) => {
+                        log::info!("successfully deleted bucket");
+                    }
+                    Err(e) => {
+                        if is_err_does_not_exist_delete_bucket(&e) {
+                            log::warn!("bucket does not exist ({})", e);
+                            return Ok(());
+                        }
+                        return Err(Error::API {
+                            message: format!("failed delete_bucket {:?}", e),
+                            retryable: errors::is_sdk_err_retryable(&e),
+                        });
+                    }
+                };
+                Ok(())
+            })
+            .await
+    }
+
+    /// Applies an expiration lifecycle rule for the given day-to-prefixes mapping.
+    pub async fn put_bucket_object_expire_configuration(
+        &self,
+        s3_bucket: &str,
+        days_to_pfxs: HashMap<i32, Vec<String>>,
+    ) -> Result<()> {
+        use aws_sdk_s3::types::{
+            BucketLifecycleConfiguration, Expiration, LifecycleRule, LifecycleRuleFilter,
+            LifecycleRuleStatus,
+        };
+
+        log::info!(
+            "applying object expire configuration on bucket '{s3_bucket}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("put_bucket_object_expire_configuration", s3_bucket, async {
+                let mut rules = Vec::new();
+                for (days, pfxs) in days_to_pfxs.iter() {
+                    for (i, pfx) in pfxs.iter().enumerate() {
+                        let rule = LifecycleRule::builder()
+                            .id(format!("expire-{days}-days-{i}"))
+                            .status(LifecycleRuleStatus::Enabled)
+                            .filter(LifecycleRuleFilter::Prefix(pfx.clone()))
+                            .expiration(Expiration::builder().days(*days).build())
+                            .build()
+                            .map_err(|e| Error::Other {
+                                message: format!("failed to build lifecycle rule {:?}", e),
+                                retryable: false,
+                            })?;
+                        rules.push(rule);
+                    }
+                }
+
+                let cfg = BucketLifecycleConfiguration::builder()
+                    .set_rules(Some(rules))
+                    .build()
+                    .map_err(|e| Error::Other {
+                        message: format!("failed to build lifecycle configuration {:?}", e),
+                        retryable: false,
+                    })?;
+
+                let ret = self
+                    .cli
+                    .put_bucket_lifecycle_configuration()
+                    .bucket(s3_bucket)
+                    .lifecycle_configuration(cfg)
+                    .send()
+                    .await;
+                match ret {
+                    Ok(_) => {
+                        log::info!("successfully applied object expire configuration");
+                        Ok(())
+                    }
+                    Err(e) => Err(Error::API {
+                        message: format!("failed put_bucket_lifecycle_configuration {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    }),
+                }
+            })
+            .await
+    }
+
+    /// Uploads a byte stream with optional metadata.
+    pub async fn put_byte_stream_with_metadata(
+        &self,
+        byte_stream: ByteStream,
+        s3_bucket: &str,
+        s3_key: &str,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<()> {
+        log::info!(
+            "uploading byte stream to 's3://{s3_bucket}/{s3_key}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("put_byte_stream_with_metadata", s3_bucket, async {
+                let mut req = self
+                    .cli
+                    .put_object()
+                    .bucket(s3_bucket)
+                    .key(s3_key)
+                    .body(byte_stream);
+                if let Some(metadata) = metadata {
+                    req = req.set_metadata(Some(metadata));
+                }
+                let ret = req.send().await;
+                match ret {
+                    Ok(_) => {
+                        log::info!("successfully uploaded byte stream");
+                        Ok(())
+                    }
+                    Err(e) => Err(Error::API {
+                        message: format!("failed put_object {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    }),
+                }
+            })
+            .await
+    }
+
+    /// Uploads raw bytes with optional metadata, retrying until `timeout` elapses.
+    pub async fn put_bytes_with_metadata_with_retries(
+        &self,
+        bytes: Vec<u8>,
+        s3_bucket: &str,
+        s3_key: &str,
+        metadata: Option<HashMap<String, String>>,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<()> {
+        self.metrics
+            .record("put_bytes_with_metadata_with_retries", s3_bucket, async {
+                let start = Instant::now();
+                let mut cnt: u32 = 0;
+                loop {
+                    let elapsed = start.elapsed();
+                    if elapsed.gt(&timeout) {
+                        break;
+                    }
+                    if cnt > 0 {
+                        sleep(interval).await;
+                    }
+                    cnt += 1;
+
+                    let byte_stream = ByteStream::from(bytes.clone());
+                    let ret = self
+                        .put_byte_stream_with_metadata(byte_stream, s3_bucket, s3_key, metadata.clone())
+                        .await;
+                    match ret {
+                        Ok(_) => return Ok(()),
+                        Err(e) => {
+                            if !e.retryable() {
+                                return Err(e);
+                            }
+                            log::warn!("put_bytes failed, retrying ({})", e);
+                        }
+                    }
+                }
+                Err(Error::Other {
+                    message: format!("failed put_bytes_with_metadata_with_retries after {cnt} tries"),
+                    retryable: false,
+                })
+            })
+            .await
+    }
+
+    /// Uploads a local file with optional metadata.
+    pub async fn put_object_with_metadata(
+        &self,
+        file_path: &str,
+        s3_bucket: &str,
+        s3_key: &str,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<()> {
+        log::info!(
+            "uploading '{file_path}' to 's3://{s3_bucket}/{s3_key}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("put_object_with_metadata", s3_bucket, async {
+                let byte_stream = ByteStream::from_path(Path::new(file_path))
+                    .await
+                    .map_err(|e| Error::Other {
+                        message: format!("failed to read '{file_path}' ({})", e),
+                        retryable: false,
+                    })?;
+                self.put_byte_stream_with_metadata(byte_stream, s3_bucket, s3_key, metadata)
+                    .await
+            })
+            .await
+    }
+
+    /// Checks whether the object exists, returning its head object output if so.
+    pub async fn exists(&self, s3_bucket: &str, s3_key: &str) -> Result<Option<HeadObjectOutput>> {
+        self.metrics
+            .record("exists", s3_bucket, async {
+                let ret = self
+                    .cli
+                    .head_object()
+                    .bucket(s3_bucket)
+                    .key(s3_key)
+                    .send()
+                    .await;
+                match ret {
+                    Ok(out) => Ok(Some(out)),
+                    Err(e) => {
+                        if is_err_does_not_exist_head_object(&e) {
+                            return Ok(None);
+                        }
+                        Err(Error::API {
+                            message: format!("failed head_object {:?}", e),
+                            retryable: errors::is_sdk_err_retryable(&e),
+                        })
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Polls `exists` until the object shows up or `timeout` elapses.
+    pub async fn exists_with_retries(
+        &self,
+        s3_bucket: &str,
+        s3_key: &str,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Option<HeadObjectOutput>> {
+        self.metrics
+            .record("exists_with_retries", s3_bucket, async {
+                let start = Instant::now();
+                let mut cnt: u32 = 0;
+                loop {
+                    let elapsed = start.elapsed();
+                    if elapsed.gt(&timeout) {
+                        break;
+                    }
+                    if cnt > 0 {
+                        sleep(interval).await;
+                    }
+                    cnt += 1;
+
+                    let head_object = self.exists(s3_bucket, s3_key).await?;
+                    if head_object.is_some() {
+                        return Ok(head_object);
+                    }
+                    log::info!("object not found yet, retrying ({cnt})");
+                }
+                Ok(None)
+            })
+            .await
+    }
+
+    /// Downloads an object to `file_path`, retrying until `timeout` elapses.
+    /// Returns `true` if the object existed and was downloaded.
+    pub async fn get_object_with_retries(
+        &self,
+        s3_bucket: &str,
+        s3_key: &str,
+        file_path: &str,
+        fail_on_not_found: bool,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<bool> {
+        self.metrics
+            .record("get_object_with_retries", s3_bucket, async {
+                let start = Instant::now();
+                let mut cnt: u32 = 0;
+                loop {
+                    let elapsed = start.elapsed();
+                    if elapsed.gt(&timeout) {
+                        break;
+                    }
+                    if cnt > 0 {
+                        sleep(interval).await;
+                    }
+                    cnt += 1;
+
+                    let ret = self
+                        .cli
+                        .get_object()
+                        .bucket(s3_bucket)
+                        .key(s3_key)
+                        .send()
+                        .await;
+                    let output = match ret {
+                        Ok(out) => out,
+                        Err(e) => {
+                            if is_err_does_not_exist_get_object(&e) {
+                                if fail_on_not_found {
+                                    log::warn!("object does not exist yet, retrying ({cnt})");
+                                    continue;
+                                }
+                                return Ok(false);
+                            }
+                            return Err(Error::API {
+                                message: format!("failed get_object {:?}", e),
+                                retryable: errors::is_sdk_err_retryable(&e),
+                            });
+                        }
+                    };
+
+                    let data = output.body.collect().await.map_err(|e| Error::Other {
+                        message: format!("failed to collect object body {:?}", e),
+                        retryable: true,
+                    })?;
+                    if let Some(parent_dir) = Path::new(file_path).parent() {
+                        fs::create_dir_all(parent_dir).map_err(|e| Error::Other {
+                            message: format!("failed to create parent dir ({})", e),
+                            retryable: false,
+                        })?;
+                    }
+                    fs::write(file_path, data.into_bytes()).map_err(|e| Error::Other {
+                        message: format!("failed to write '{file_path}' ({})", e),
+                        retryable: false,
+                    })?;
+                    return Ok(true);
+                }
+                Ok(false)
+            })
+            .await
+    }
+
+    /// Downloads an executable, setting the executable bit afterwards.
+    /// If the destination already exists and `overwrite` is false, only the
+    /// permission bit is (re-)applied and the download is skipped.
+    pub async fn download_executable_with_retries(
+        &self,
+        s3_bucket: &str,
+        s3_key: &str,
+        file_path: &str,
+        overwrite: bool,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<bool> {
+        self.metrics
+            .record("download_executable_with_retries", s3_bucket, async {
+                if !overwrite && Path::new(file_path).exists() {
+                    log::info!("'{file_path}' already exists, skipping download");
+                } else {
+                    let downloaded = self
+                        .get_object_with_retries(s3_bucket, s3_key, file_path, true, timeout, interval)
+                        .await?;
+                    if !downloaded {
+                        return Ok(false);
+                    }
+                }
+
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mut perms = fs::metadata(file_path)
+                        .map_err(|e| Error::Other {
+                            message: format!("failed to stat '{file_path}' ({})", e),
+                            retryable: false,
+                        })?
+                        .permissions();
+                    perms.set_mode(0o755);
+                    fs::set_permissions(file_path, perms).map_err(|e| Error::Other {
+                        message: format!("failed to chmod '{file_path}' ({})", e),
+                        retryable: false,
+                    })?;
+                }
+
+                Ok(true)
+            })
+            .await
+    }
+
+    /// Lists objects under an optional prefix.
+    pub async fn list_objects(&self, s3_bucket: &str, prefix: Option<&str>) -> Result<Vec<Object>> {
+        log::info!(
+            "listing objects in 's3://{s3_bucket}' with prefix '{:?}' in region '{}'",
+            prefix,
+            self.region
+        );
+        self.metrics
+            .record("list_objects", s3_bucket, async {
+                let mut objects = Vec::new();
+                let mut continuation_token: Option<String> = None;
+                loop {
+                    let mut req = self.cli.list_objects_v2().bucket(s3_bucket);
+                    if let Some(pfx) = prefix {
+                        req = req.prefix(pfx);
+                    }
+                    if let Some(token) = &continuation_token {
+                        req = req.continuation_token(token);
+                    }
+                    let ret = req.send().await.map_err(|e| Error::API {
+                        message: format!("failed list_objects_v2 {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+
+                    if let Some(contents) = ret.contents {
+                        objects.extend(contents);
+                    }
+                    if ret.is_truncated.unwrap_or(false) {
+                        continuation_token = ret.next_continuation_token;
+                    } else {
+                        break;
+                    }
+                }
+                Ok(objects)
+            })
+            .await
+    }
+
+    /// Lists objects under an optional prefix, yielding one page at a time
+    /// so callers can process huge listings without buffering the whole
+    /// bucket in memory.
+    pub fn list_objects_stream<'a>(
+        &'a self,
+        s3_bucket: &'a str,
+        prefix: Option<&'a str>,
+    ) -> impl Stream<Item = Result<Vec<Object>>> + 'a {
+        async_stream::try_stream! {
+            let mut continuation_token: Option<String> = None;
+            loop {
+                let mut req = self.cli.list_objects_v2().bucket(s3_bucket);
+                if let Some(pfx) = prefix {
+                    req = req.prefix(pfx);
+                }
+                if let Some(token) = &continuation_token {
+                    req = req.continuation_token(token);
+                }
+                let ret = req.send().await.map_err(|e| Error::API {
+                    message: format!("failed list_objects_v2 {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+
+                yield ret.contents.unwrap_or_default();
+
+                if ret.is_truncated.unwrap_or(false) {
+                    continuation_token = ret.next_continuation_token;
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Enumerates all buckets owned by the caller.
+    pub async fn list_buckets(&self) -> Result<Vec<Bucket>> {
+        log::info!("listing buckets in region '{}'", self.region);
+        self.metrics
+            .record("list_buckets", &self.region, async {
+                let ret = self.cli.list_buckets().send().await.map_err(|e| Error::API {
+                    message: format!("failed list_buckets {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+                Ok(ret.buckets.unwrap_or_default())
+            })
+            .await
+    }
+
+    /// Deletes all objects under an optional prefix.
+    pub async fn delete_objects(&self, s3_bucket: &str, prefix: Option<&str>) -> Result<()> {
+        log::info!(
+            "deleting objects in 's3://{s3_bucket}' with prefix '{:?}' in region '{}'",
+            prefix,
+            self.region
+        );
+        self.metrics
+            .record("delete_objects", s3_bucket, async {
+                let objects = self.list_objects(s3_bucket, prefix).await?;
+                for obj in objects.iter() {
+                    let Some(key) = &obj.key else { continue };
+                    let ret = self
+                        .cli
+                        .delete_object()
+                        .bucket(s3_bucket)
+                        .key(key)
+                        .send()
+                        .await;
+                    if let Err(e) = ret {
+                        return Err(Error::API {
+                            message: format!("failed delete_object '{key}' {:?}", e),
+                            retryable: errors::is_sdk_err_retryable(&e),
+                        });
+                    }
+                }
+                log::info!("successfully deleted {} objects", objects.len());
+                Ok(())
+            })
+            .await
+    }
+
+    /// Generates a presigned GET URL that a third party can use to download
+    /// the object without AWS credentials, valid for `expires_in`.
+    pub async fn presign_get(
+        &self,
+        s3_bucket: &str,
+        s3_key: &str,
+        expires_in: Duration,
+    ) -> Result<String> {
+        self.metrics
+            .record("presign_get", s3_bucket, async {
+                let presigning_config =
+                    PresigningConfig::expires_in(expires_in).map_err(|e| Error::Other {
+                        message: format!("invalid presigning expiry {:?}", e),
+                        retryable: false,
+                    })?;
+                let presigned = self
+                    .cli
+                    .get_object()
+                    .bucket(s3_bucket)
+                    .key(s3_key)
+                    .presigned(presigning_config)
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed to presign get_object {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                Ok(presigned.uri().to_string())
+            })
+            .await
+    }
+
+    /// Generates a presigned PUT URL that a third party can use to upload an
+    /// object without AWS credentials, valid for `expires_in`.
+    pub async fn presign_put(
+        &self,
+        s3_bucket: &str,
+        s3_key: &str,
+        expires_in: Duration,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<String> {
+        self.metrics
+            .record("presign_put", s3_bucket, async {
+                let presigning_config =
+                    PresigningConfig::expires_in(expires_in).map_err(|e| Error::Other {
+                        message: format!("invalid presigning expiry {:?}", e),
+                        retryable: false,
+                    })?;
+                let mut req = self.cli.put_object().bucket(s3_bucket).key(s3_key);
+                if let Some(metadata) = metadata {
+                    req = req.set_metadata(Some(metadata));
+                }
+                let presigned = req
+                    .presigned(presigning_config)
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed to presign put_object {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                Ok(presigned.uri().to_string())
+            })
+            .await
+    }
+
+    /// Copies an object server-side, without downloading/re-uploading its
+    /// bytes. Falls back to an `UploadPartCopy` multipart loop for sources
+    /// larger than `MAX_SINGLE_COPY_SIZE`, since a single `CopyObject` is
+    /// capped at 5 GiB.
+    ///
+    /// `replace_metadata`, when set, overrides the destination's metadata
+    /// (`MetadataDirective::Replace`); otherwise the source's metadata is
+    /// copied unchanged (`MetadataDirective::Copy`).
+    pub async fn copy_object(
+        &self,
+        src_bucket: &str,
+        src_key: &str,
+        dst_bucket: &str,
+        dst_key: &str,
+        replace_metadata: Option<HashMap<String, String>>,
+    ) -> Result<()> {
+        self.metrics
+            .record("copy_object", src_bucket, async {
+                let head = self.exists(src_bucket, src_key).await?.ok_or_else(|| Error::Other {
+                    message: format!("source object 's3://{src_bucket}/{src_key}' does not exist"),
+                    retryable: false,
+                })?;
+                let size = head.content_length.unwrap_or(0) as u64;
+
+                if size > MAX_SINGLE_COPY_SIZE {
+                    log::info!(
+                        "'s3://{src_bucket}/{src_key}' is {size} bytes, exceeding the {MAX_SINGLE_COPY_SIZE}-byte single CopyObject limit; falling back to multipart UploadPartCopy",
+                    );
+                    return self
+                        .copy_object_multipart(src_bucket, src_key, dst_bucket, dst_key, size, replace_metadata)
+                        .await;
+                }
+
+                log::info!(
+                    "copying 's3://{src_bucket}/{src_key}' to 's3://{dst_bucket}/{dst_key}' in region '{}'",
+                    self.region
+                );
+                let copy_source = format!("{src_bucket}/{src_key}");
+                let mut req = self
+                    .cli
+                    .copy_object()
+                    .bucket(dst_bucket)
+                    .key(dst_key)
+                    .copy_source(copy_source);
+                if let Some(metadata) = replace_metadata {
+                    req = req
+                        .metadata_directive(MetadataDirective::Replace)
+                        .set_metadata(Some(metadata));
+                } else {
+                    req = req.metadata_directive(MetadataDirective::Copy);
+                }
+                req.send().await.map_err(|e| Error::API {
+                    message: format!("failed copy_object {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+
+                log::info!("successfully copied object");
+                Ok(())
+            })
+            .await
+    }
+
+    /// Copies an object larger than the single-request `CopyObject` limit by
+    /// driving `create_multipart_upload`/`upload_part_copy`/`complete_multipart_upload`
+    /// with byte-range `copy_source_range`s.
+    async fn copy_object_multipart(
+        &self,
+        src_bucket: &str,
+        src_key: &str,
+        dst_bucket: &str,
+        dst_key: &str,
+        size: u64,
+        replace_metadata: Option<HashMap<String, String>>,
+    ) -> Result<()> {
+        let mut create_req = self
+            .cli
+            .create_multipart_upload()
+            .bucket(dst_bucket)
+            .key(dst_key);
+        if let Some(metadata) = replace_metadata {
+            create_req = create_req.set_metadata(Some(metadata));
+        }
+        let create_out = create_req.send().await.map_err(|e| Error::API {
+            message: format!("failed create_multipart_upload {:?}", e),
+            retryable: errors::is_sdk_err_retryable(&e),
+        })?;
+        let upload_id = create_out.upload_id.ok_or_else(|| Error::Other {
+            message: "create_multipart_upload returned no upload_id".to_string(),
+            retryable: false,
+        })?;
+
+        let copy_source = format!("{src_bucket}/{src_key}");
+        let part_size = DEFAULT_MULTIPART_PART_SIZE as u64;
+        let num_parts = size.div_ceil(part_size).max(1);
+
+        let mut completed_parts = Vec::with_capacity(num_parts as usize);
+        for part_number in 1..=num_parts as i32 {
+            let start = (part_number as u64 - 1) * part_size;
+            let end = (start + part_size).min(size) - 1;
+            let byte_range = format!("bytes={start}-{end}");
+
+            let ret = self
+                .cli
+                .upload_part_copy()
+                .bucket(dst_bucket)
+                .key(dst_key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .copy_source(&copy_source)
+                .copy_source_range(&byte_range)
+                .send()
+                .await;
+            let out = match ret {
+                Ok(out) => out,
+                Err(e) => {
+                    log::warn!(
+                        "upload_part_copy failed, aborting upload_id '{upload_id}' ({:?})",
+                        e
+                    );
+                    self.abort_multipart_upload(dst_bucket, dst_key, &upload_id)
+                        .await?;
+                    return Err(Error::API {
+                        message: format!("failed upload_part_copy for part {part_number} {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+            };
+            let e_tag = out
+                .copy_part_result
+                .and_then(|r| r.e_tag)
+                .ok_or_else(|| Error::Other {
+                    message: format!("upload_part_copy for part {part_number} returned no e_tag"),
+                    retryable: false,
+                })?;
+            completed_parts.push(CompletedPart::builder().e_tag(e_tag).part_number(part_number).build());
+        }
+
+        let completed = CompletedMultipartUpload::builder()
+            .set_parts(Some(completed_parts))
+            .build();
+        let ret = self
+            .cli
+            .complete_multipart_upload()
+            .bucket(dst_bucket)
+            .key(dst_key)
+            .upload_id(&upload_id)
+            .multipart_upload(completed)
+            .send()
+            .await;
+        if let Err(e) = ret {
+            log::warn!(
+                "complete_multipart_upload failed, aborting upload_id '{upload_id}' ({:?})",
+                e
+            );
+            self.abort_multipart_upload(dst_bucket, dst_key, &upload_id)
+                .await?;
+            return Err(Error::API {
+                message: format!("failed complete_multipart_upload {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        }
+
+        log::info!("successfully copied object via multipart UploadPartCopy");
+        Ok(())
+    }
+
+    /// Copies an object then deletes the source, i.e. a server-side move.
+    pub async fn move_object(
+        &self,
+        src_bucket: &str,
+        src_key: &str,
+        dst_bucket: &str,
+        dst_key: &str,
+        replace_metadata: Option<HashMap<String, String>>,
+    ) -> Result<()> {
+        self.metrics
+            .record("move_object", src_bucket, async {
+                self.copy_object(src_bucket, src_key, dst_bucket, dst_key, replace_metadata)
+                    .await?;
+                self.cli
+                    .delete_object()
+                    .bucket(src_bucket)
+                    .key(src_key)
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed delete_object '{src_key}' {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                log::info!("successfully moved 's3://{src_bucket}/{src_key}' to 's3://{dst_bucket}/{dst_key}'");
+                Ok(())
+            })
+            .await
+    }
+
+    /// Uploads a large local file using S3 multipart upload, streaming the
+    /// file in `part_size` chunks (clamped to `MIN_MULTIPART_PART_SIZE` for
+    /// all but the last part) with up to `DEFAULT_MULTIPART_CONCURRENCY`
+    /// parts in flight at once.
+    ///
+    /// If any part fails to upload after retries, the in-progress upload is
+    /// aborted via `abort_multipart_upload` so S3 does not keep billing for
+    /// the dangling parts.
+    pub async fn put_object_multipart_with_retries(
+        &self,
+        file_path: &str,
+        s3_bucket: &str,
+        s3_key: &str,
+        part_size: Option<usize>,
+        metadata: Option<HashMap<String, String>>,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<()> {
+        self.metrics
+            .record("put_object_multipart_with_retries", s3_bucket, async {
+                let part_size = part_size
+                    .unwrap_or(DEFAULT_MULTIPART_PART_SIZE)
+                    .max(MIN_MULTIPART_PART_SIZE);
+
+                let file_size = fs::metadata(file_path)
+                    .map_err(|e| Error::Other {
+                        message: format!("failed to stat '{file_path}' ({})", e),
+                        retryable: false,
+                    })?
+                    .len() as usize;
+
+                log::info!(
+                    "starting multipart upload of '{file_path}' ({file_size} bytes) to 's3://{s3_bucket}/{s3_key}' with part size {part_size}",
+                );
+
+                let mut create_req = self.cli.create_multipart_upload().bucket(s3_bucket).key(s3_key);
+                if let Some(metadata) = metadata {
+                    create_req = create_req.set_metadata(Some(metadata));
+                }
+                let create_out = create_req.send().await.map_err(|e| Error::API {
+                    message: format!("failed create_multipart_upload {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+                let upload_id = create_out.upload_id.ok_or_else(|| Error::Other {
+                    message: "create_multipart_upload returned no upload_id".to_string(),
+                    retryable: false,
+                })?;
+
+                let result = self
+                    .upload_parts_with_retries(
+                        file_path, s3_bucket, s3_key, &upload_id, file_size, part_size, timeout, interval,
+                    )
+                    .await;
+
+                let completed_parts = match result {
+                    Ok(parts) => parts,
+                    Err(e) => {
+                        log::warn!(
+                            "multipart upload failed, aborting upload_id '{upload_id}' ({})",
+                            e
+                        );
+                        self.abort_multipart_upload(s3_bucket, s3_key, &upload_id)
+                            .await?;
+                        return Err(e);
+                    }
+                };
+
+                let completed = CompletedMultipartUpload::builder()
+                    .set_parts(Some(completed_parts))
+                    .build();
+                let ret = self
+                    .cli
+                    .complete_multipart_upload()
+                    .bucket(s3_bucket)
+                    .key(s3_key)
+                    .upload_id(&upload_id)
+                    .multipart_upload(completed)
+                    .send()
+                    .await;
+                if let Err(e) = ret {
+                    log::warn!(
+                        "complete_multipart_upload failed, aborting upload_id '{upload_id}' ({:?})",
+                        e
+                    );
+                    self.abort_multipart_upload(s3_bucket, s3_key, &upload_id)
+                        .await?;
+                    return Err(Error::API {
+                        message: format!("failed complete_multipart_upload {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+
+                log::info!("successfully completed multipart upload of '{file_path}'");
+                Ok(())
+            })
+            .await
+    }
+
+    /// Uploads every part of `file_path` with bounded concurrency, retrying
+    /// each part individually until `timeout` elapses.
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_parts_with_retries(
+        &self,
+        file_path: &str,
+        s3_bucket: &str,
+        s3_key: &str,
+        upload_id: &str,
+        file_size: usize,
+        part_size: usize,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Vec<CompletedPart>> {
+        let num_parts = file_size.div_ceil(part_size).max(1);
+        let semaphore = Arc::new(Semaphore::new(DEFAULT_MULTIPART_CONCURRENCY));
+        let mut tasks = Vec::with_capacity(num_parts);
+
+        for part_number in 1..=num_parts as i32 {
+            let offset = (part_number as usize - 1) * part_size;
+            let this_part_size = part_size.min(file_size.saturating_sub(offset));
+
+            let cli = self.cli.clone();
+            let s3_bucket = s3_bucket.to_string();
+            let s3_key = s3_key.to_string();
+            let upload_id = upload_id.to_string();
+            let file_path = file_path.to_string();
+            let semaphore = semaphore.clone();
+
+            let task = tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                upload_one_part_with_retries(
+                    &cli,
+                    &s3_bucket,
+                    &s3_key,
+                    &upload_id,
+                    &file_path,
+                    offset,
+                    this_part_size,
+                    part_number,
+                    timeout,
+                    interval,
+                )
+                .await
+            });
+            tasks.push(task);
+        }
+
+        let mut completed_parts = Vec::with_capacity(num_parts);
+        for task in tasks {
+            let part = task.await.map_err(|e| Error::Other {
+                message: format!("multipart upload task panicked ({})", e),
+                retryable: false,
+            })??;
+            completed_parts.push(part);
+        }
+        completed_parts.sort_by_key(|p| p.part_number.unwrap_or(0));
+        Ok(completed_parts)
+    }
+
+    /// Aborts an in-progress multipart upload so S3 stops billing for its parts.
+    async fn abort_multipart_upload(
+        &self,
+        s3_bucket: &str,
+        s3_key: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        let ret = self
+            .cli
+            .abort_multipart_upload()
+            .bucket(s3_bucket)
+            .key(s3_key)
+            .upload_id(upload_id)
+            .send()
+            .await;
+        if let Err(e) = ret {
+            return Err(Error::API {
+                message: format!("failed abort_multipart_upload {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        }
+        log::info!("successfully aborted multipart upload_id '{upload_id}'");
+        Ok(())
+    }
+}
+
+/// Reads exactly `len` bytes at `offset` from `file_path` and uploads them as
+/// one part, retrying until `timeout` elapses.
+#[allow(clippy::too_many_arguments)]
+async fn upload_one_part_with_retries(
+    cli: &Client,
+    s3_bucket: &str,
+    s3_key: &str,
+    upload_id: &str,
+    file_path: &str,
+    offset: usize,
+    len: usize,
+    part_number: i32,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<CompletedPart> {
+    use std::io::Seek;
+
+    let mut buf = vec![0u8; len];
+    {
+        let mut f = File::open(file_path).map_err(|e| Error::Other {
+            message: format!("failed to open '{file_path}' ({})", e),
+            retryable: false,
+        })?;
+        f.seek(std::io::SeekFrom::Start(offset as u64))
+            .map_err(|e| Error::Other {
+                message: format!("failed to seek '{file_path}' ({})", e),
+                retryable: false,
+            })?;
+        f.read_exact(&mut buf).map_err(|e| Error::Other {
+            message: format!("failed to read '{file_path}' ({})", e),
+            retryable: false,
+        })?;
+    }
+
+    let start = Instant::now();
+    let mut cnt: u32 = 0;
+    loop {
+        let elapsed = start.elapsed();
+        if cnt > 0 && elapsed.gt(&timeout) {
+            break;
+        }
+        if cnt > 0 {
+            sleep(interval).await;
+        }
+        cnt += 1;
+
+        let ret = cli
+            .upload_part()
+            .bucket(s3_bucket)
+            .key(s3_key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(buf.clone()))
+            .send()
+            .await;
+        match ret {
+            Ok(out) => {
+                let e_tag = out.e_tag.ok_or_else(|| Error::Other {
+                    message: format!("upload_part for part {part_number} returned no e_tag"),
+                    retryable: false,
+                })?;
+                return Ok(CompletedPart::builder()
+                    .e_tag(e_tag)
+                    .part_number(part_number)
+                    .build());
+            }
+            Err(e) => {
+                let retryable = errors::is_sdk_err_retryable(&e);
+                if !retryable {
+                    return Err(Error::API {
+                        message: format!("failed upload_part for part {part_number} {:?}", e),
+                        retryable,
+                    });
+                }
+                log::warn!("upload_part for part {part_number} failed, retrying ({})", e);
+            }
+        }
+    }
+    Err(Error::Other {
+        message: format!("failed to upload part {part_number} after {cnt} tries"),
+        retryable: false,
+    })
+}
+
+#[inline]
+fn is_err_does_not_exist_head_bucket(
+    e: &aws_smithy_runtime_api::client::result::SdkError<
+        aws_sdk_s3::operation::head_bucket::HeadBucketError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    use aws_smithy_runtime_api::client::result::SdkError;
+    matches!(e, SdkError::ServiceError(err) if err.err().is_not_found())
+}
+
+#[inline]
+fn is_err_already_owned_create_bucket(
+    e: &aws_smithy_runtime_api::client::result::SdkError<
+        aws_sdk_s3::operation::create_bucket::CreateBucketError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    use aws_smithy_runtime_api::client::result::SdkError;
+    matches!(
+        e,
+        SdkError::ServiceError(err) if err.err().is_bucket_already_owned_by_you()
+    )
+}
+
+#[inline]
+fn is_err_does_not_exist_delete_bucket(
+    e: &aws_smithy_runtime_api::client::result::SdkError<
+        aws_sdk_s3::operation::delete_bucket::DeleteBucketError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    use aws_smithy_runtime_api::client::result::SdkError;
+    matches!(e, SdkError::ServiceError(err) if err.err().is_no_such_bucket())
+}
+
+#[inline]
+fn is_err_does_not_exist_head_object(
+    e: &aws_smithy_runtime_api::client::result::SdkError<
+        aws_sdk_s3::operation::head_object::HeadObjectError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    use aws_smithy_runtime_api::client::result::SdkError;
+    matches!(e, SdkError::ServiceError(err) if err.err().is_not_found())
+}
+
+#[inline]
+fn is_err_does_not_exist_get_object(
+    e: &aws_smithy_runtime_api::client::result::SdkError<
+        aws_sdk_s3::operation::get_object::GetObjectError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    use aws_smithy_runtime_api::client::result::SdkError;
+    matches!(e, SdkError::ServiceError(err) if err.err().is_no_such_key())
+}
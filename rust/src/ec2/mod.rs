@@ -0,0 +1,185 @@
+pub mod plugins;
+pub mod ssm;
+pub mod sweep;
+
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+use crate::errors::{self, Error, Result};
+use crate::observability::ApiMetrics;
+use aws_sdk_ec2::Client;
+use aws_types::SdkConfig as AwsSdkConfig;
+use serde::{Deserialize, Serialize};
+
+/// Defines the CPU architecture (and, where relevant, GPU vendor) of the
+/// target instance, used to pick the right plugin install scripts.
+#[derive(
+    Deserialize, Serialize, std::clone::Clone, std::cmp::Eq, std::fmt::Debug, std::hash::Hash,
+)]
+pub enum ArchType {
+    #[serde(rename = "amd64")]
+    Amd64,
+    #[serde(rename = "arm64")]
+    Arm64,
+    #[serde(rename = "amd64-nvidia")]
+    Amd64Nvidia,
+    #[serde(rename = "arm64-nvidia")]
+    Arm64Nvidia,
+
+    Unknown(String),
+}
+
+impl ArchType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ArchType::Amd64 => "amd64",
+            ArchType::Arm64 => "arm64",
+            ArchType::Amd64Nvidia => "amd64-nvidia",
+            ArchType::Arm64Nvidia => "arm64-nvidia",
+            ArchType::Unknown(s) => s.as_ref(),
+        }
+    }
+
+    /// Returns true if the architecture has an attached Nvidia GPU.
+    pub fn is_nvidia(&self) -> bool {
+        matches!(self, ArchType::Amd64Nvidia | ArchType::Arm64Nvidia)
+    }
+}
+
+impl AsRef<str> for ArchType {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Defines the target instance's OS, used to pick the right package manager
+/// and paths in the plugin install scripts.
+#[derive(
+    Deserialize, Serialize, std::clone::Clone, std::cmp::Eq, std::fmt::Debug, std::hash::Hash,
+)]
+pub enum OsType {
+    #[serde(rename = "ubuntu20.04")]
+    Ubuntu2004,
+    #[serde(rename = "ubuntu22.04")]
+    Ubuntu2204,
+    #[serde(rename = "al2")]
+    Al2,
+    #[serde(rename = "al2023")]
+    Al2023,
+
+    Unknown(String),
+}
+
+impl OsType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            OsType::Ubuntu2004 => "ubuntu20.04",
+            OsType::Ubuntu2204 => "ubuntu22.04",
+            OsType::Al2 => "al2",
+            OsType::Al2023 => "al2023",
+            OsType::Unknown(s) => s.as_ref(),
+        }
+    }
+}
+
+impl AsRef<str> for OsType {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Implements AWS EC2 manager.
+#[derive(Clone)]
+pub struct Manager {
+    pub region: String,
+    cli: Client,
+    metrics: ApiMetrics,
+}
+
+impl std::fmt::Debug for Manager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Manager").field("region", &self.region).finish()
+    }
+}
+
+impl Manager {
+    pub fn new(shared_config: &AwsSdkConfig) -> Self {
+        let cli = Client::new(shared_config);
+        Self {
+            region: shared_config.region().unwrap().to_string(),
+            cli,
+            metrics: ApiMetrics::new("aws_manager::ec2"),
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        self.cli.clone()
+    }
+
+    /// Creates an EC2 key pair and writes its PEM-encoded private key to
+    /// `priv_key_path` with owner-only permissions.
+    pub async fn create_key_pair(&self, key_name: &str, priv_key_path: &str) -> Result<()> {
+        log::info!(
+            "creating EC2 key pair '{key_name}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("create_key_pair", key_name, async {
+                let ret = self.cli.create_key_pair().key_name(key_name).send().await;
+                let out = ret.map_err(|e| Error::API {
+                    message: format!("failed create_key_pair {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+                let priv_key = out.key_material.ok_or_else(|| Error::Other {
+                    message: "create_key_pair returned no key_material".to_string(),
+                    retryable: false,
+                })?;
+
+                if let Some(parent_dir) = Path::new(priv_key_path).parent() {
+                    fs::create_dir_all(parent_dir).map_err(|e| Error::Other {
+                        message: format!("failed to create parent dir ({})", e),
+                        retryable: false,
+                    })?;
+                }
+                fs::write(priv_key_path, priv_key).map_err(|e| Error::Other {
+                    message: format!("failed to write '{priv_key_path}' ({})", e),
+                    retryable: false,
+                })?;
+                let mut perms = fs::metadata(priv_key_path)
+                    .map_err(|e| Error::Other {
+                        message: format!("failed to stat '{priv_key_path}' ({})", e),
+                        retryable: false,
+                    })?
+                    .permissions();
+                perms.set_mode(0o600);
+                fs::set_permissions(priv_key_path, perms).map_err(|e| Error::Other {
+                    message: format!("failed to chmod '{priv_key_path}' ({})", e),
+                    retryable: false,
+                })?;
+
+                log::info!("successfully created key pair");
+                Ok(())
+            })
+            .await
+    }
+
+    /// Deletes the EC2 key pair. Succeeds silently if it does not exist.
+    pub async fn delete_key_pair(&self, key_name: &str) -> Result<()> {
+        log::info!(
+            "deleting EC2 key pair '{key_name}' in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("delete_key_pair", key_name, async {
+                let ret = self.cli.delete_key_pair().key_name(key_name).send().await;
+                if let Err(e) = ret {
+                    return Err(Error::API {
+                        message: format!("failed delete_key_pair {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::info!("successfully deleted key pair (or it did not exist)");
+                Ok(())
+            })
+            .await
+    }
+}
@@ -0,0 +1,32 @@
+//! Opt-in per-plugin checkpointing so a re-run of the init script (some
+//! AMIs re-execute user-data on every stop/start) resumes from the first
+//! incomplete plugin instead of redoing work that already succeeded.
+//!
+//! `create`'s `resumable_init` flag wraps every plugin's block in `wrap`;
+//! cleanup plugins additionally emit `clear_snippet` so a baked AMI's
+//! next boot starts with no stale checkpoints.
+
+/// Directory holding one empty sentinel file per completed plugin,
+/// named after the plugin.
+pub const CHECKPOINT_DIR: &str = "/var/lib/aws-manager/completed";
+
+/// Wraps `script` (a single plugin's already-`progress::wrap`ped block)
+/// so it's skipped entirely if `<CHECKPOINT_DIR>/<plugin_name>` exists,
+/// and the sentinel is written only after `script` exits zero.
+pub fn wrap(plugin_name: &str, script: &str) -> String {
+    format!(
+        "if [ -f \"{CHECKPOINT_DIR}/{plugin_name}\" ]; then\n\
+         echo \"skipping {plugin_name}, checkpoint already present\"\n\
+         else\n\
+         {script}\n\
+         mkdir -p \"{CHECKPOINT_DIR}\"\n\
+         touch \"{CHECKPOINT_DIR}/{plugin_name}\"\n\
+         fi\n"
+    )
+}
+
+/// Clears every recorded checkpoint; appended to a cleanup plugin's
+/// block so an AMI baked from this run boots with a clean slate.
+pub fn clear_snippet() -> String {
+    format!("rm -rf \"{CHECKPOINT_DIR}\"\n")
+}
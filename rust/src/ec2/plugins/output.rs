@@ -0,0 +1,52 @@
+//! Alternative renderings of the init script `create` assembles, besides
+//! the default raw bash blob.
+
+/// How `create` should render the resolved, ordered plugin set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// A raw bash script, passed as EC2 user-data as-is.
+    #[default]
+    Bash,
+    /// A `#cloud-config` document that writes the bash script to disk via
+    /// `write_files` and runs it via `bootcmd`/`runcmd`, for callers whose
+    /// launch path expects cloud-init user-data rather than a bash
+    /// shebang.
+    CloudConfig,
+}
+
+const CLOUD_CONFIG_SCRIPT_PATH: &str = "/var/lib/cloud/scripts/per-instance/init.sh";
+
+/// Renders `bash_contents` (as produced by `create`) in `format`.
+pub fn render(format: OutputFormat, bash_contents: &str) -> String {
+    match format {
+        OutputFormat::Bash => bash_contents.to_string(),
+        OutputFormat::CloudConfig => render_cloud_config(bash_contents),
+    }
+}
+
+/// Wraps `bash_contents` as a `#cloud-config` `write_files` entry (as a
+/// YAML literal block, so no base64/dependency is needed), created via
+/// `bootcmd` and executed via `runcmd`.
+fn render_cloud_config(bash_contents: &str) -> String {
+    let indented = bash_contents
+        .lines()
+        .map(|line| format!("      {line}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let mut out = String::new();
+    out.push_str("#cloud-config\n");
+    out.push_str("write_files:\n");
+    out.push_str(&format!("  - path: {CLOUD_CONFIG_SCRIPT_PATH}\n"));
+    out.push_str("    permissions: '0755'\n");
+    out.push_str("    content: |\n");
+    out.push_str(&indented);
+    out.push('\n');
+    out.push_str("bootcmd:\n");
+    out.push_str(&format!(
+        "  - mkdir -p $(dirname {CLOUD_CONFIG_SCRIPT_PATH})\n"
+    ));
+    out.push_str("runcmd:\n");
+    out.push_str(&format!("  - {CLOUD_CONFIG_SCRIPT_PATH}\n"));
+    out
+}
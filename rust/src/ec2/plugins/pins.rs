@@ -0,0 +1,60 @@
+//! Pinned versions/digests for artifacts a plugin's script downloads, so a
+//! generated init script verifies `sha256sum` before trusting (and
+//! installing) what it fetched, instead of trusting the network/mirror.
+//!
+//! Each pinned plugin's script is expected to download its artifact to
+//! `/tmp/<plugin-name>.download` before installing it; `create` appends
+//! the `sha256sum -c`-and-abort check right after that plugin's own
+//! render output, using that path convention.
+
+use std::collections::HashMap;
+
+/// A pinned artifact version and its expected SHA256 digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactPin {
+    pub version: String,
+    pub sha256: String,
+}
+
+impl ArtifactPin {
+    pub fn new(version: impl Into<String>, sha256: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+            sha256: sha256.into(),
+        }
+    }
+}
+
+/// Maps plugin name to its pinned artifact. Built from `default_pins()`
+/// and merged with any caller-supplied overrides in `create`.
+pub type PinMap = HashMap<String, ArtifactPin>;
+
+/// Default pins shipped with this crate. Empty until a maintainer records
+/// a real digest for a given plugin's pinned version here.
+///
+/// `create` only emits a `sha256sum -c` verification snippet for a plugin
+/// that has an entry in the merged pin map (see `ctx.pins.get(p.name())`
+/// at the call site), so a plugin with no entry here simply isn't
+/// digest-verified -- it does *not* fail closed with a guaranteed bad
+/// checksum. Callers that want tamper protection today should pass a
+/// real digest as an override to `create`; shipping a fake placeholder
+/// digest here would instead make every default-pinned plugin's init
+/// script abort unconditionally, even on a correct download.
+pub fn default_pins() -> PinMap {
+    PinMap::new()
+}
+
+/// Renders the bash snippet that verifies `/tmp/<plugin-name>.download`
+/// against `pin`'s digest, aborting the init script (before it ever
+/// reaches `INIT_SCRIPT_COMPLETE_MSG`) on a mismatch.
+pub fn verification_snippet(plugin_name: &str, pin: &ArtifactPin) -> String {
+    let download_path = format!("/tmp/{plugin_name}.download");
+    format!(
+        "echo \"verifying sha256 digest of {plugin_name} {version} artifact\"\n\
+         echo \"{sha256}  {download_path}\" | sha256sum -c - || {{ echo \"{plugin_name} artifact failed digest verification\"; exit 1; }}\n",
+        plugin_name = plugin_name,
+        version = pin.version,
+        sha256 = pin.sha256,
+        download_path = download_path,
+    )
+}
@@ -0,0 +1,242 @@
+//! A structured, machine-readable progress/completion protocol for the
+//! generated init script: each plugin's block emits one JSON line per
+//! phase (`start`/`ok`/`fail`) to a well-known log file and to stdout
+//! behind a grep-able prefix, instead of (or rather, in addition to) the
+//! legacy [`super::INIT_SCRIPT_COMPLETE_MSG`] marker this crate has
+//! always printed at the end of a successful run.
+
+use serde::{Deserialize, Serialize};
+
+/// Durable log every plugin's `start`/`ok`/`fail` line is appended to, in
+/// addition to stdout, so a status check doesn't depend on having
+/// captured the instance's console output.
+pub const PROGRESS_LOG_PATH: &str = "/var/log/aws-manager-init-script-status.jsonl";
+
+/// Every progress line written to stdout is prefixed with this so a
+/// consumer tailing the console/serial log can `grep` it out from the
+/// rest of the script's (non-machine-readable) output.
+pub const PROGRESS_STDOUT_PREFIX: &str = "INIT_SCRIPT_STATUS ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Start,
+    Ok,
+    Fail,
+}
+
+/// One parsed progress line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginStatus {
+    pub plugin: String,
+    pub rank: u32,
+    pub phase: Phase,
+    /// Shell `date -u +%Y-%m-%dT%H:%M:%SZ` output; not parsed into a
+    /// `chrono`/`time` type since this crate doesn't otherwise depend on
+    /// one.
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Wraps `script` (the bash a single plugin rendered) so it emits a
+/// `start` line, then either an `ok` or a `fail` line (with the shell
+/// exit code as the error) depending on whether `script` succeeded.
+///
+/// `script` runs with `set +e` so a failure doesn't abort the init
+/// script before the `fail` line is written; the wrapper re-raises the
+/// same exit code afterward so overall script failure is unchanged.
+pub fn wrap(plugin_name: &str, rank: u32, script: &str) -> String {
+    let start_line = emit_line_cmd(plugin_name, rank, Phase::Start, None);
+    let ok_line = emit_line_cmd(plugin_name, rank, Phase::Ok, None);
+    let fail_line = emit_line_cmd(plugin_name, rank, Phase::Fail, Some("exit code $__rc"));
+
+    format!(
+        "{start_line}\n\
+         set +e\n\
+         (\n\
+         {script}\n\
+         )\n\
+         __rc=$?\n\
+         set -e\n\
+         if [ \"$__rc\" -eq 0 ]; then\n\
+         {ok_line}\n\
+         else\n\
+         {fail_line}\n\
+         exit \"$__rc\"\n\
+         fi\n"
+    )
+}
+
+/// Renders the `echo`-and-append shell command for one progress line.
+/// `error_expr` is a literal shell expression (already quoted/escaped by
+/// the caller) substituted into the JSON, or omitted entirely when absent.
+fn emit_line_cmd(plugin_name: &str, rank: u32, phase: Phase, error_expr: Option<&str>) -> String {
+    let phase_str = match phase {
+        Phase::Start => "start",
+        Phase::Ok => "ok",
+        Phase::Fail => "fail",
+    };
+    let error_field = error_expr
+        .map(|e| format!(",\\\"error\\\":\\\"{e}\\\""))
+        .unwrap_or_default();
+    format!(
+        "__line=\"{{\\\"plugin\\\":\\\"{plugin_name}\\\",\\\"rank\\\":{rank},\\\"phase\\\":\\\"{phase_str}\\\",\\\"timestamp\\\":\\\"$(date -u +%Y-%m-%dT%H:%M:%SZ)\\\"{error_field}}}\"\n\
+         echo \"{PROGRESS_STDOUT_PREFIX}$__line\"\n\
+         echo \"$__line\" >> {PROGRESS_LOG_PATH}"
+    )
+}
+
+/// Sentinel plugin name used for the final, whole-script completion line
+/// (see `super::INIT_SCRIPT_COMPLETE_MSG`) rather than a single plugin's.
+pub const COMPLETE_MARKER: &str = "__init_script_complete__";
+
+/// Renders the final `ok` progress line marking the whole init script as
+/// complete, so the legacy `INIT_SCRIPT_COMPLETE_MSG` echo is driven by
+/// this same protocol instead of being a bare, unstructured `echo`.
+pub fn emit_complete() -> String {
+    emit_line_cmd(COMPLETE_MARKER, 0, Phase::Ok, None)
+}
+
+/// Parses every well-formed progress line out of `stream` (stdout or the
+/// contents of [`PROGRESS_LOG_PATH`]), stripping [`PROGRESS_STDOUT_PREFIX`]
+/// where present and silently skipping lines that aren't a valid
+/// `PluginStatus` (the rest of the script's own, non-protocol output).
+pub fn parse_stream(stream: &str) -> Vec<PluginStatus> {
+    stream
+        .lines()
+        .filter_map(|line| {
+            let json = line.strip_prefix(PROGRESS_STDOUT_PREFIX).unwrap_or(line);
+            serde_json::from_str::<PluginStatus>(json.trim()).ok()
+        })
+        .collect()
+}
+
+/// One plugin's `start` line paired with its `ok`/`fail` line, reduced to
+/// the shape a build-log consumer (pass/fail, timing, exit code) wants --
+/// the same information a `@@@STEP_START@.../@@@STEP_END@...@@@`-style
+/// banner would carry, just derived from this crate's existing
+/// `start`/`ok`/`fail` JSON-line protocol instead of a second, parallel
+/// marker format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepResult {
+    pub plugin: String,
+    pub rank: u32,
+    pub ok: bool,
+    pub start_timestamp: String,
+    /// `None` if the stream ended (or the next plugin's `start` line
+    /// appeared) before this plugin's `ok`/`fail` line was seen.
+    pub end_timestamp: Option<String>,
+    /// Parsed out of the `fail` line's `error` field (`"exit code N"`,
+    /// see `wrap`); `None` on success or when a completion line was
+    /// never observed.
+    pub exit_code: Option<i32>,
+}
+
+/// Pairs up `parse_stream`'s `start`/`ok`/`fail` lines into one
+/// `StepResult` per plugin. A `start` with no matching completion line
+/// (the script was killed, or truncated, mid-plugin) yields `ok: false`
+/// with `end_timestamp: None`.
+pub fn parse_step_results(stream: &str) -> Vec<StepResult> {
+    let statuses = parse_stream(stream);
+    let mut results = Vec::new();
+    let mut pending: Option<&PluginStatus> = None;
+
+    for status in &statuses {
+        match status.phase {
+            Phase::Start => {
+                if let Some(p) = pending.take() {
+                    results.push(incomplete_step(p));
+                }
+                pending = Some(status);
+            }
+            Phase::Ok | Phase::Fail => {
+                let start = pending.take();
+                results.push(StepResult {
+                    plugin: status.plugin.clone(),
+                    rank: status.rank,
+                    ok: status.phase == Phase::Ok,
+                    start_timestamp: start
+                        .map(|s| s.timestamp.clone())
+                        .unwrap_or_else(|| status.timestamp.clone()),
+                    end_timestamp: Some(status.timestamp.clone()),
+                    exit_code: parse_exit_code(&status.error),
+                });
+            }
+        }
+    }
+    if let Some(p) = pending {
+        results.push(incomplete_step(p));
+    }
+    results
+}
+
+fn incomplete_step(start: &PluginStatus) -> StepResult {
+    StepResult {
+        plugin: start.plugin.clone(),
+        rank: start.rank,
+        ok: false,
+        start_timestamp: start.timestamp.clone(),
+        end_timestamp: None,
+        exit_code: None,
+    }
+}
+
+fn parse_exit_code(error: &Option<String>) -> Option<i32> {
+    error.as_ref()?.strip_prefix("exit code ")?.trim().parse().ok()
+}
+
+#[test]
+fn test_parse_step_results_pairs_start_and_ok() {
+    let stream = format!(
+        "{PROGRESS_STDOUT_PREFIX}{}\n{PROGRESS_STDOUT_PREFIX}{}\n",
+        serde_json::to_string(&PluginStatus {
+            plugin: "docker".to_string(),
+            rank: 28,
+            phase: Phase::Start,
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            error: None,
+        })
+        .unwrap(),
+        serde_json::to_string(&PluginStatus {
+            plugin: "docker".to_string(),
+            rank: 28,
+            phase: Phase::Fail,
+            timestamp: "2024-01-01T00:00:05Z".to_string(),
+            error: Some("exit code 1".to_string()),
+        })
+        .unwrap(),
+    );
+
+    let steps = parse_step_results(&stream);
+    assert_eq!(steps.len(), 1);
+    assert!(!steps[0].ok);
+    assert_eq!(steps[0].exit_code, Some(1));
+    assert_eq!(steps[0].start_timestamp, "2024-01-01T00:00:00Z");
+    assert_eq!(steps[0].end_timestamp.as_deref(), Some("2024-01-01T00:00:05Z"));
+}
+
+#[test]
+fn test_parse_stream_mixed_output() {
+    // Simulates what the shell actually prints at runtime once `wrap`'s
+    // `echo` commands are evaluated -- not `wrap`'s bash source itself.
+    let start = PluginStatus {
+        plugin: "docker".to_string(),
+        rank: 28,
+        phase: Phase::Start,
+        timestamp: "2024-01-01T00:00:00Z".to_string(),
+        error: None,
+    };
+    let ok = PluginStatus {
+        phase: Phase::Ok,
+        ..start.clone()
+    };
+    let stream = format!(
+        "some unrelated line from the script\n{PROGRESS_STDOUT_PREFIX}{}\nanother unrelated line\n{PROGRESS_STDOUT_PREFIX}{}\n",
+        serde_json::to_string(&start).unwrap(),
+        serde_json::to_string(&ok).unwrap(),
+    );
+
+    let statuses = parse_stream(&stream);
+    assert_eq!(statuses, vec![start, ok]);
+}
@@ -0,0 +1,137 @@
+//! Offline record/replay harness for generated init scripts.
+//!
+//! Rather than executing the assembled bash against a shimmed `PATH` and
+//! real IMDS/AWS CLI calls, this statically traces, in order, every line
+//! that invokes `aws ...`, `curl http://169.254.169.254/...`, or
+//! `ec2-metadata ...`, and pairs each against a caller-supplied fixture --
+//! so a test can assert a plugin issues the expected external-command
+//! sequence entirely offline.
+//!
+//! This is a static trace of the script text, not a real interpreter: it
+//! does not evaluate shell conditionals/loops, so a command inside an
+//! `if`/`while` body is still recorded unconditionally. That's sufficient
+//! to assert "`static-volume-provisioner` issues attach/mkfs/mount in
+//! this order", which is what this harness is for.
+
+use std::collections::HashMap;
+
+/// Canned responses for external commands a script might invoke, keyed
+/// by a substring match against the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Fixtures {
+    responses: HashMap<String, String>,
+}
+
+impl Fixtures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a canned response for any recorded command line
+    /// containing `command_substring`. Later calls to `with` take
+    /// precedence over earlier ones for the same substring.
+    pub fn with(mut self, command_substring: impl Into<String>, response: impl Into<String>) -> Self {
+        self.responses.insert(command_substring.into(), response.into());
+        self
+    }
+
+    fn lookup(&self, command: &str) -> Option<&str> {
+        self.responses
+            .iter()
+            .find(|(substring, _)| command.contains(substring.as_str()))
+            .map(|(_, response)| response.as_str())
+    }
+}
+
+/// One external command the script would have issued, and the fixture
+/// response it was paired with (if any).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    pub kind: CallKind,
+    pub command: String,
+    pub response: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    AwsCli,
+    Imds,
+    Ec2Metadata,
+}
+
+/// The ordered trace of external commands `contents` would issue, and
+/// which ones had no matching fixture.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub calls: Vec<RecordedCall>,
+}
+
+impl ReplayReport {
+    /// Commands recorded but not covered by any fixture in `Fixtures`.
+    pub fn unmatched(&self) -> Vec<&RecordedCall> {
+        self.calls.iter().filter(|c| c.response.is_none()).collect()
+    }
+}
+
+/// Statically traces `contents` (as produced by `create`) for external
+/// commands, pairing each against `fixtures`.
+pub fn replay(contents: &str, fixtures: &Fixtures) -> ReplayReport {
+    let mut calls = Vec::new();
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        let kind = if trimmed.contains("169.254.169.254") {
+            Some(CallKind::Imds)
+        } else if starts_with_word(trimmed, "ec2-metadata") {
+            Some(CallKind::Ec2Metadata)
+        } else if starts_with_word(trimmed, "aws") {
+            Some(CallKind::AwsCli)
+        } else {
+            None
+        };
+
+        if let Some(kind) = kind {
+            calls.push(RecordedCall {
+                kind,
+                command: trimmed.to_string(),
+                response: fixtures.lookup(trimmed).map(|s| s.to_string()),
+            });
+        }
+    }
+    ReplayReport { calls }
+}
+
+fn starts_with_word(line: &str, word: &str) -> bool {
+    line.strip_prefix(word)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+#[test]
+fn test_replay_records_ordered_calls_with_fixtures() {
+    let contents = "\
+echo preparing volume
+aws ec2 attach-volume --volume-id vol-123 --device /dev/xvdb
+mkfs -t ext4 /dev/xvdb
+mount /dev/xvdb /data
+curl -s http://169.254.169.254/latest/meta-data/instance-id
+";
+    let fixtures = Fixtures::new()
+        .with("attach-volume", "{\"VolumeId\":\"vol-123\",\"State\":\"attaching\"}")
+        .with("instance-id", "i-0123456789abcdef0");
+
+    let report = replay(contents, &fixtures);
+    assert_eq!(report.calls.len(), 2);
+    assert_eq!(report.calls[0].kind, CallKind::AwsCli);
+    assert_eq!(
+        report.calls[0].response.as_deref(),
+        Some("{\"VolumeId\":\"vol-123\",\"State\":\"attaching\"}")
+    );
+    assert_eq!(report.calls[1].kind, CallKind::Imds);
+    assert!(report.unmatched().is_empty());
+}
+
+#[test]
+fn test_replay_reports_unmatched_calls() {
+    let contents = "aws s3 ls s3://bucket/\n";
+    let report = replay(contents, &Fixtures::new());
+    assert_eq!(report.unmatched().len(), 1);
+}
@@ -0,0 +1,291 @@
+//! SSM Session Manager sessions against instances this crate provisions --
+//! interactive shells and port forwarding, on top of the `ssm-agent`
+//! plugin that's already installed at AMI bake time.
+
+use std::process::{Command, Stdio};
+
+use crate::errors::{self, Error, Result};
+use crate::observability::ApiMetrics;
+use aws_sdk_ssm::Client;
+use aws_types::SdkConfig as AwsSdkConfig;
+
+/// `aws ssm start-session` document used for a plain interactive shell.
+const PORT_FORWARDING_DOCUMENT: &str = "AWS-StartPortForwardingSession";
+
+/// Implements AWS SSM Session Manager.
+#[derive(Clone)]
+pub struct Manager {
+    pub region: String,
+    cli: Client,
+    metrics: ApiMetrics,
+}
+
+impl std::fmt::Debug for Manager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Manager").field("region", &self.region).finish()
+    }
+}
+
+/// The session parameters the SDK hands back, and the `session-manager-plugin`
+/// invocation a caller would run to open it.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub session_id: String,
+    pub stream_url: String,
+    pub token_value: String,
+    instance_id: String,
+    port_forward: Option<(u16, u16)>,
+    /// The `aws ssm start-session ...` (or `session-manager-plugin ...`)
+    /// command line a caller can run directly, whether or not the plugin
+    /// binary is actually present on this host. For display/copy-paste
+    /// only -- `attach` builds and runs its own argv rather than parsing
+    /// this string, so it is never handed to a shell by this crate.
+    pub connection_command: String,
+}
+
+impl Manager {
+    pub fn new(shared_config: &AwsSdkConfig) -> Self {
+        let cli = Client::new(shared_config);
+        Self {
+            region: shared_config.region().unwrap().to_string(),
+            cli,
+            metrics: ApiMetrics::new("aws_manager::ssm"),
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        self.cli.clone()
+    }
+
+    /// Returns the `aws ssm start-session --target <instance_id>`
+    /// invocation, for callers that just want the command line (e.g. to
+    /// copy to a clipboard or hand to another process) without this crate
+    /// calling the SDK on their behalf.
+    pub fn connection_string(&self, instance_id: &str) -> String {
+        format!(
+            "aws ssm start-session --region {} --target {instance_id}",
+            self.region
+        )
+    }
+
+    /// Opens an interactive shell session via `StartSession`, returning
+    /// its parameters and a ready-to-run `connection_command`. This does
+    /// not itself attach to the session -- call `attach` on the result to
+    /// actually shell out to `session-manager-plugin`, or run
+    /// `connection_command` another way.
+    pub async fn start_session(&self, instance_id: &str) -> Result<Session> {
+        log::info!("starting SSM session with '{instance_id}' in region '{}'", self.region);
+        self.metrics
+            .record("start_session", instance_id, async {
+                let out = self
+                    .cli
+                    .start_session()
+                    .target(instance_id)
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed start_session {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                self.to_session(out.session_id, out.stream_url, out.token_value, instance_id, None)
+            })
+            .await
+    }
+
+    /// Opens a port-forwarding session from `local_port` on the caller's
+    /// machine to `remote_port` on the instance.
+    pub async fn start_port_forwarding(
+        &self,
+        instance_id: &str,
+        remote_port: u16,
+        local_port: u16,
+    ) -> Result<Session> {
+        log::info!(
+            "starting SSM port-forwarding session with '{instance_id}' ({remote_port} -> {local_port}) in region '{}'",
+            self.region
+        );
+        self.metrics
+            .record("start_port_forwarding", instance_id, async {
+                let out = self
+                    .cli
+                    .start_session()
+                    .target(instance_id)
+                    .document_name(PORT_FORWARDING_DOCUMENT)
+                    .parameters("portNumber", vec![remote_port.to_string()])
+                    .parameters("localPortNumber", vec![local_port.to_string()])
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed start_session (port forwarding) {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                self.to_session(
+                    out.session_id,
+                    out.stream_url,
+                    out.token_value,
+                    instance_id,
+                    Some((remote_port, local_port)),
+                )
+            })
+            .await
+    }
+
+    /// Attaches to `session` interactively by spawning
+    /// `session-manager-plugin` directly (never through a shell) with
+    /// this process's stdio, blocking until the session ends. Returns
+    /// `Ok(None)` without spawning anything if the plugin binary isn't on
+    /// `PATH` -- the caller should fall back to running
+    /// `session.connection_command` (an `aws ssm start-session`
+    /// invocation) themselves in that case.
+    pub fn attach(&self, session: &Session) -> std::io::Result<Option<std::process::ExitStatus>> {
+        if !session_manager_plugin_available() {
+            log::warn!(
+                "session-manager-plugin not found on PATH -- not attaching, run session.connection_command instead"
+            );
+            return Ok(None);
+        }
+
+        let mut cmd = Command::new("session-manager-plugin");
+        cmd.arg(session_response_json(session)?)
+            .arg(&self.region)
+            .arg("StartSession");
+        if let Some((remote_port, local_port)) = session.port_forward {
+            cmd.arg(port_forward_target_json(
+                &session.instance_id,
+                remote_port,
+                local_port,
+            )?);
+        }
+
+        let status = cmd
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+        Ok(Some(status))
+    }
+
+    /// Terminates a session previously returned by `start_session`/
+    /// `start_port_forwarding`.
+    pub async fn terminate_session(&self, session_id: &str) -> Result<()> {
+        log::info!("terminating SSM session '{session_id}'");
+        self.cli
+            .terminate_session()
+            .session_id(session_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed terminate_session {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        Ok(())
+    }
+
+    fn to_session(
+        &self,
+        session_id: Option<String>,
+        stream_url: Option<String>,
+        token_value: Option<String>,
+        instance_id: &str,
+        port_forward: Option<(u16, u16)>,
+    ) -> Result<Session> {
+        let session_id = session_id.ok_or_else(|| Error::Other {
+            message: "start_session returned no session_id".to_string(),
+            retryable: false,
+        })?;
+        let stream_url = stream_url.unwrap_or_default();
+        let token_value = token_value.unwrap_or_default();
+
+        let session = Session {
+            session_id,
+            stream_url,
+            token_value,
+            instance_id: instance_id.to_string(),
+            port_forward,
+            connection_command: String::new(),
+        };
+
+        let connection_command = if session_manager_plugin_available() {
+            let mut cmd = format!(
+                "session-manager-plugin {} {} StartSession",
+                shell_single_quote(&session_response_json(&session)?),
+                self.region,
+            );
+            if let Some((remote_port, local_port)) = port_forward {
+                cmd.push(' ');
+                cmd.push_str(&shell_single_quote(&port_forward_target_json(
+                    instance_id,
+                    remote_port,
+                    local_port,
+                )?));
+            }
+            cmd
+        } else {
+            log::warn!("session-manager-plugin not found on PATH -- returning raw session parameters instead");
+            self.connection_string(instance_id)
+        };
+
+        Ok(Session {
+            connection_command,
+            ..session
+        })
+    }
+}
+
+/// Whether the `session-manager-plugin` binary the AWS CLI shells out to
+/// is available on this host's `PATH`.
+fn session_manager_plugin_available() -> bool {
+    Command::new("session-manager-plugin")
+        .arg("--version")
+        .output()
+        .is_ok()
+}
+
+/// The `session-manager-plugin` session-response JSON argument describing
+/// `session`, built with `serde_json` rather than string interpolation so
+/// none of the AWS-returned fields can break out of the JSON structure.
+fn session_response_json(session: &Session) -> std::io::Result<String> {
+    serde_json::to_string(&serde_json::json!({
+        "SessionId": session.session_id,
+        "StreamUrl": session.stream_url,
+        "TokenValue": session.token_value,
+    }))
+    .map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("failed to encode session response as JSON ({e})"),
+        )
+    })
+}
+
+/// The `session-manager-plugin` target-parameters JSON argument for a
+/// port-forwarding session, built with `serde_json` for the same reason
+/// as `session_response_json`.
+fn port_forward_target_json(
+    instance_id: &str,
+    remote_port: u16,
+    local_port: u16,
+) -> std::io::Result<String> {
+    serde_json::to_string(&serde_json::json!({
+        "Target": instance_id,
+        "DocumentName": PORT_FORWARDING_DOCUMENT,
+        "Parameters": {
+            "portNumber": [remote_port.to_string()],
+            "localPortNumber": [local_port.to_string()],
+        },
+    }))
+    .map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("failed to encode port-forwarding target as JSON ({e})"),
+        )
+    })
+}
+
+/// Wraps `s` in single quotes for display in `connection_command`,
+/// escaping any embedded single quote the standard POSIX-shell way.
+/// `attach` never executes this string -- it builds its own argv -- so
+/// this only needs to be correct for a caller who copies and pastes it.
+fn shell_single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
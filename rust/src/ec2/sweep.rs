@@ -0,0 +1,305 @@
+//! Tag-scoped teardown of resources this crate provisions (instances,
+//! security groups, key pairs, AMIs, snapshots).
+//!
+//! Every sweep is dry-run by default: `plan` always just lists what
+//! matches, and `execute` (the only function that mutates anything)
+//! requires the caller to pass `confirm: true`, deleting in
+//! dependency-safe order -- instances before the security groups and key
+//! pairs they reference.
+
+use crate::errors::{self, Error, Result};
+use aws_sdk_ec2::Client;
+use std::collections::HashMap;
+
+/// The kind of EC2 resource a sweep should consider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Instance,
+    SecurityGroup,
+    KeyPair,
+    Image,
+    Snapshot,
+}
+
+/// Selects which resources a sweep targets: only those carrying every
+/// tag in `tags`, restricted to `resource_types`.
+#[derive(Debug, Clone, Default)]
+pub struct SweepFilter {
+    pub tags: HashMap<String, String>,
+    pub resource_types: Vec<ResourceKind>,
+}
+
+/// One resource a sweep found, identified by its ARN-equivalent ID (for
+/// EC2 that's the `i-...`/`sg-...`/`ami-...`/`snap-...`/key-pair-name
+/// identifier, not a true ARN).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SweepCandidate {
+    pub kind: ResourceKind,
+    pub id: String,
+}
+
+/// The set of matching resources found for a `SweepFilter`, always
+/// produced before anything is deleted.
+#[derive(Debug, Clone, Default)]
+pub struct SweepPlan {
+    pub candidates: Vec<SweepCandidate>,
+}
+
+impl SweepPlan {
+    pub fn of_kind(&self, kind: ResourceKind) -> Vec<&SweepCandidate> {
+        self.candidates.iter().filter(|c| c.kind == kind).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+}
+
+/// Queries each requested service for resources matching `filter.tags`,
+/// without deleting anything.
+///
+/// Requires `filter.tags` to be non-empty: EC2's `describe_*` APIs treat
+/// an empty filter list as "no filter," so an empty/mistyped tag map
+/// would otherwise silently scope the sweep to every resource of the
+/// requested kinds in the whole account/region.
+pub async fn plan(cli: &Client, filter: &SweepFilter) -> Result<SweepPlan> {
+    if filter.tags.is_empty() {
+        return Err(Error::Other {
+            message: "sweep filter must specify at least one tag, refusing to match every resource in the account/region".to_string(),
+            retryable: false,
+        });
+    }
+
+    let mut candidates = Vec::new();
+    let tag_filters: Vec<_> = filter
+        .tags
+        .iter()
+        .map(|(k, v)| {
+            aws_sdk_ec2::types::Filter::builder()
+                .name(format!("tag:{k}"))
+                .values(v.clone())
+                .build()
+        })
+        .collect();
+
+    for kind in &filter.resource_types {
+        match kind {
+            ResourceKind::Instance => {
+                let out = cli
+                    .describe_instances()
+                    .set_filters(Some(tag_filters.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed describe_instances {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                for reservation in out.reservations.unwrap_or_default() {
+                    for inst in reservation.instances.unwrap_or_default() {
+                        if let Some(id) = inst.instance_id {
+                            candidates.push(SweepCandidate { kind: *kind, id });
+                        }
+                    }
+                }
+            }
+            ResourceKind::SecurityGroup => {
+                let out = cli
+                    .describe_security_groups()
+                    .set_filters(Some(tag_filters.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed describe_security_groups {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                for sg in out.security_groups.unwrap_or_default() {
+                    if let Some(id) = sg.group_id {
+                        candidates.push(SweepCandidate { kind: *kind, id });
+                    }
+                }
+            }
+            ResourceKind::KeyPair => {
+                let out = cli
+                    .describe_key_pairs()
+                    .set_filters(Some(tag_filters.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed describe_key_pairs {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                for kp in out.key_pairs.unwrap_or_default() {
+                    if let Some(name) = kp.key_name {
+                        candidates.push(SweepCandidate { kind: *kind, id: name });
+                    }
+                }
+            }
+            ResourceKind::Image => {
+                let out = cli
+                    .describe_images()
+                    .owners("self")
+                    .set_filters(Some(tag_filters.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed describe_images {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                for image in out.images.unwrap_or_default() {
+                    if let Some(id) = image.image_id {
+                        candidates.push(SweepCandidate { kind: *kind, id });
+                    }
+                }
+            }
+            ResourceKind::Snapshot => {
+                let out = cli
+                    .describe_snapshots()
+                    .owner_ids("self")
+                    .set_filters(Some(tag_filters.clone()))
+                    .send()
+                    .await
+                    .map_err(|e| Error::API {
+                        message: format!("failed describe_snapshots {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    })?;
+                for snap in out.snapshots.unwrap_or_default() {
+                    if let Some(id) = snap.snapshot_id {
+                        candidates.push(SweepCandidate { kind: *kind, id });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(SweepPlan { candidates })
+}
+
+/// One candidate's deletion outcome, as reported by `execute`.
+#[derive(Debug)]
+pub struct SweepOutcome {
+    pub candidate: SweepCandidate,
+    pub result: Result<()>,
+}
+
+/// Deletes every resource in `sweep_plan`, in dependency-safe order
+/// (instances first, then the security groups and key pairs that may
+/// reference them, then images, then the snapshots backing those
+/// images). Does nothing unless `confirm` is `true`, so a caller must
+/// have reviewed the plan (e.g. printed it to the user) first.
+///
+/// A failure deleting one candidate (e.g. it was already deleted out of
+/// band) does not stop the sweep -- every candidate is attempted, and
+/// its individual outcome is reported back in the returned `Vec` in the
+/// same order the candidates were deleted in.
+pub async fn execute(
+    cli: &Client,
+    sweep_plan: &SweepPlan,
+    confirm: bool,
+) -> Result<Vec<SweepOutcome>> {
+    if !confirm {
+        log::info!(
+            "sweep not confirmed, leaving {} candidate(s) untouched",
+            sweep_plan.candidates.len()
+        );
+        return Ok(Vec::new());
+    }
+
+    let mut outcomes = Vec::new();
+
+    for candidate in sweep_plan.of_kind(ResourceKind::Instance) {
+        log::info!("terminating instance '{}'", candidate.id);
+        let result = cli
+            .terminate_instances()
+            .instance_ids(candidate.id.clone())
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::API {
+                message: format!("failed terminate_instances {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        report(&mut outcomes, candidate, result);
+    }
+
+    for candidate in sweep_plan.of_kind(ResourceKind::SecurityGroup) {
+        log::info!("deleting security group '{}'", candidate.id);
+        let result = cli
+            .delete_security_group()
+            .group_id(candidate.id.clone())
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::API {
+                message: format!("failed delete_security_group {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        report(&mut outcomes, candidate, result);
+    }
+
+    for candidate in sweep_plan.of_kind(ResourceKind::KeyPair) {
+        log::info!("deleting key pair '{}'", candidate.id);
+        let result = cli
+            .delete_key_pair()
+            .key_name(candidate.id.clone())
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::API {
+                message: format!("failed delete_key_pair {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        report(&mut outcomes, candidate, result);
+    }
+
+    for candidate in sweep_plan.of_kind(ResourceKind::Image) {
+        log::info!("deregistering image '{}'", candidate.id);
+        let result = cli
+            .deregister_image()
+            .image_id(candidate.id.clone())
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::API {
+                message: format!("failed deregister_image {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        report(&mut outcomes, candidate, result);
+    }
+
+    for candidate in sweep_plan.of_kind(ResourceKind::Snapshot) {
+        log::info!("deleting snapshot '{}'", candidate.id);
+        let result = cli
+            .delete_snapshot()
+            .snapshot_id(candidate.id.clone())
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::API {
+                message: format!("failed delete_snapshot {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            });
+        report(&mut outcomes, candidate, result);
+    }
+
+    let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+    if failed > 0 {
+        log::warn!(
+            "sweep completed with {failed}/{} candidate(s) failing to delete",
+            outcomes.len()
+        );
+    }
+
+    Ok(outcomes)
+}
+
+/// Logs and records one candidate's delete outcome without aborting the
+/// rest of the sweep.
+fn report(outcomes: &mut Vec<SweepOutcome>, candidate: &SweepCandidate, result: Result<()>) {
+    if let Err(e) = &result {
+        log::warn!("failed to delete {:?} '{}': {e}", candidate.kind, candidate.id);
+    }
+    outcomes.push(SweepOutcome {
+        candidate: candidate.clone(),
+        result,
+    });
+}
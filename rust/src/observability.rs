@@ -0,0 +1,87 @@
+//! Lightweight request/error/duration instrumentation for manager
+//! operations, gated behind the `observability` feature so the
+//! `opentelemetry` dependency and its runtime cost are opt-in.
+
+use crate::errors::Result;
+
+/// Per-manager set of OpenTelemetry instruments (a request counter, an
+/// error counter, and a request-duration histogram), all tagged with the
+/// operation name and a target (bucket, region, instance id, etc.).
+///
+/// Compiles down to a zero-cost no-op when the `observability` feature is
+/// disabled, so call sites never need their own `#[cfg(...)]`.
+#[derive(Clone)]
+pub struct ApiMetrics {
+    #[cfg(feature = "observability")]
+    inner: std::sync::Arc<Inner>,
+}
+
+#[cfg(feature = "observability")]
+struct Inner {
+    requests: opentelemetry::metrics::Counter<u64>,
+    errors: opentelemetry::metrics::Counter<u64>,
+    duration: opentelemetry::metrics::Histogram<f64>,
+}
+
+impl ApiMetrics {
+    /// Creates the metrics instruments under `meter_name`
+    /// (e.g., "aws_manager::s3", "aws_manager::ec2").
+    pub fn new(meter_name: &'static str) -> Self {
+        #[cfg(feature = "observability")]
+        {
+            let meter = opentelemetry::global::meter(meter_name);
+            Self {
+                inner: std::sync::Arc::new(Inner {
+                    requests: meter.u64_counter("requests_total").init(),
+                    errors: meter.u64_counter("errors_total").init(),
+                    duration: meter.f64_histogram("request_duration_seconds").init(),
+                }),
+            }
+        }
+        #[cfg(not(feature = "observability"))]
+        {
+            let _ = meter_name;
+            Self {}
+        }
+    }
+
+    /// Times `fut`, recording a request counter, a duration histogram, and
+    /// (on failure) an error counter, tagged with `operation` and `target`.
+    /// Also opens a tracing span around the call so retries and latency are
+    /// visible in a collector.
+    pub async fn record<T>(
+        &self,
+        operation: &str,
+        target: &str,
+        fut: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        #[cfg(feature = "observability")]
+        {
+            use opentelemetry::KeyValue;
+            use tracing::Instrument;
+
+            let labels = [
+                KeyValue::new("operation", operation.to_string()),
+                KeyValue::new("target", target.to_string()),
+            ];
+            self.inner.requests.add(1, &labels);
+
+            let span = tracing::info_span!("aws_manager_api_call", operation, target);
+            let start = std::time::Instant::now();
+            let result = fut.instrument(span).await;
+            self.inner
+                .duration
+                .record(start.elapsed().as_secs_f64(), &labels);
+            if result.is_err() {
+                self.inner.errors.add(1, &labels);
+            }
+            result
+        }
+        #[cfg(not(feature = "observability"))]
+        {
+            let _ = operation;
+            let _ = target;
+            fut.await
+        }
+    }
+}